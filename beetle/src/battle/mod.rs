@@ -0,0 +1,384 @@
+mod network_events;
+mod spawner;
+mod ui_events;
+
+use std::collections::VecDeque;
+
+use common::{
+    battle::{BattleAction, BattleEnd, BattleOutcome, BattleSnapshot, TurnResult},
+    validation::usernames_match,
+    NetworkID,
+};
+use crossbeam_channel::unbounded;
+use legion::{system, systems::CommandBuffer, Schedule};
+use macroquad::{
+    prelude::{Color, BLACK, DARKGRAY, GOLD, GREEN, MAROON},
+    shapes::{draw_rectangle, draw_rectangle_lines},
+    text::draw_text,
+    window::{screen_height, screen_width},
+};
+
+use crate::{
+    draw_clear_color_system,
+    overworld::return_to_menu_when_disconnected_system,
+    ui::{add_ui_layout_systems, add_ui_rendering_systems, despawn_ui_system},
+    ClearColor, Schedules,
+};
+
+use self::{
+    network_events::handle_battle_events_system,
+    spawner::{
+        show_return_button_system, show_spectator_controls_system, spawn_battle_ui_system,
+        update_battle_status_system,
+    },
+    ui_events::{handle_battle_ui_events_system, BattleUIEvent, BattleUIEventChannel},
+};
+
+const MAX_LOG_LINES: usize = 6;
+
+pub fn battle_schedules() -> Schedules {
+    let enter_schedule = Schedule::builder()
+        .add_system(initialize_battle_resources_system())
+        .add_system(despawn_ui_system())
+        .flush()
+        .add_system(spawn_battle_ui_system())
+        .build();
+
+    let mut tick_sbuilder = Schedule::builder();
+    add_ui_layout_systems::<BattleUIEvent>(&mut tick_sbuilder);
+    tick_sbuilder
+        .add_system(handle_battle_events_system::<client::Connection>())
+        .add_system(return_to_menu_when_disconnected_system())
+        .flush()
+        .add_system(handle_battle_ui_events_system())
+        .add_system(update_battle_status_system())
+        .add_system(show_spectator_controls_system())
+        .add_system(show_return_button_system());
+    let tick_schedule = tick_sbuilder.build();
+
+    let mut render_sbuilder = Schedule::builder();
+    render_sbuilder
+        .add_thread_local(draw_clear_color_system())
+        .add_thread_local(draw_combatants_system());
+    add_ui_rendering_systems::<BattleUIEvent>(&mut render_sbuilder);
+    render_sbuilder.add_thread_local(draw_turn_log_system());
+    let render_schedule = render_sbuilder.build();
+
+    Schedules {
+        enter_schedule,
+        tick_schedule,
+        render_schedule,
+    }
+}
+
+#[system]
+fn initialize_battle_resources(commands: &mut CommandBuffer) {
+    commands.exec_mut(|_, resources| {
+        resources.insert(ClearColor(Color::from_rgba(150, 110, 90, 255)));
+        resources.insert(BattleState::default());
+
+        let (s, r) = unbounded();
+        resources.insert(BattleUIEventChannel(s, r));
+    });
+}
+
+/// Everything the battle screen knows about the duel we are in or
+/// watching.
+#[derive(Default)]
+pub struct BattleState {
+    snapshot: Option<BattleSnapshot>,
+    /// Only set when we are one of the combatants.
+    own_id: Option<NetworkID>,
+    outcome: Option<BattleOutcome>,
+    log: VecDeque<String>,
+    spectators: u32,
+}
+
+impl BattleState {
+    fn start(&mut self, snapshot: BattleSnapshot, own_name: &str) {
+        self.own_id = snapshot
+            .combatants
+            .iter()
+            .find(|combatant| usernames_match(&combatant.name, own_name))
+            .map(|combatant| combatant.id);
+        self.add_log_line("The duel has begun!".to_string());
+        self.snapshot = Some(snapshot);
+    }
+
+    fn apply_turn(&mut self, result: TurnResult, snapshot: BattleSnapshot) {
+        self.add_log_line(result.description);
+        self.snapshot = Some(snapshot);
+    }
+
+    fn end(&mut self, outcome: BattleOutcome) {
+        self.outcome = Some(outcome);
+        let line = self.describe_outcome(outcome);
+        self.add_log_line(line);
+    }
+
+    fn add_log_line(&mut self, line: String) {
+        self.log.push_back(line);
+
+        while self.log.len() > MAX_LOG_LINES {
+            self.log.pop_front();
+        }
+    }
+
+    fn name_of(&self, id: NetworkID) -> &str {
+        self.snapshot
+            .as_ref()
+            .and_then(|snapshot| snapshot.combatant(id))
+            .map(|combatant| combatant.name.as_str())
+            .unwrap_or("Your opponent")
+    }
+
+    fn is_spectating(&self) -> bool {
+        self.snapshot.is_some() && self.own_id.is_none()
+    }
+
+    fn is_own_turn(&self) -> bool {
+        self.snapshot
+            .as_ref()
+            .is_some_and(|snapshot| Some(snapshot.turn) == self.own_id)
+    }
+
+    fn describe_outcome(&self, outcome: BattleOutcome) -> String {
+        let won = Some(outcome.winner) == self.own_id;
+        let winner = self.name_of(outcome.winner);
+        let loser = self.name_of(outcome.loser);
+
+        if self.is_spectating() {
+            return match outcome.reason {
+                BattleEnd::Defeated => format!("{winner} defeated {loser}!"),
+                BattleEnd::Forfeited => format!("{loser} forfeited. {winner} wins!"),
+                BattleEnd::Disconnected => format!("{loser} left the server. {winner} wins!"),
+            };
+        }
+
+        match (outcome.reason, won) {
+            (BattleEnd::Defeated, true) => format!("You defeated {loser}!"),
+            (BattleEnd::Forfeited, true) => format!("{loser} forfeited. You win!"),
+            (BattleEnd::Disconnected, true) => format!("{loser} left the server. You win!"),
+            (BattleEnd::Forfeited, false) => "You forfeited the duel.".to_string(),
+            (_, false) => format!("You were defeated by {winner}."),
+        }
+    }
+
+    /// The line shown at the top of the screen.
+    fn status(&self) -> String {
+        let Some(snapshot) = &self.snapshot else {
+            return "Waiting for the duel to begin...".to_string();
+        };
+
+        let status = match self.outcome {
+            Some(outcome) if self.is_spectating() => {
+                format!("{} wins!", self.name_of(outcome.winner))
+            }
+            Some(outcome) if Some(outcome.winner) == self.own_id => "Victory!".to_string(),
+            Some(_) => "Defeat".to_string(),
+            None if self.is_own_turn() => format!("Round {}: Your turn!", snapshot.round),
+            None => format!(
+                "Round {}: Waiting for {}...",
+                snapshot.round,
+                self.name_of(snapshot.turn)
+            ),
+        };
+
+        match self.spectators {
+            0 => status,
+            count => format!("{status} ({count} watching)"),
+        }
+    }
+
+    /// Catch actions the server would refuse before sending them, so
+    /// the player hears about it on the battle screen.
+    fn check_action(&self, action: BattleAction) -> Result<(), &'static str> {
+        let Some(snapshot) = &self.snapshot else {
+            return Err("The duel has not started yet.");
+        };
+        if self.outcome.is_some() {
+            return Err("The duel is already over.");
+        }
+        if self.is_spectating() {
+            return Err("You are only watching this duel.");
+        }
+        if action == BattleAction::Forfeit {
+            return Ok(());
+        }
+        if !self.is_own_turn() {
+            return Err("It is not your turn.");
+        }
+
+        let ability_used = snapshot
+            .combatant(snapshot.turn)
+            .is_some_and(|own| own.ability_used);
+        if action == BattleAction::Ability && ability_used {
+            return Err("You have already used your heavy strike.");
+        }
+
+        Ok(())
+    }
+}
+
+#[system]
+fn draw_combatants(#[resource] battle: &BattleState) {
+    const BAR_WIDTH: f32 = 320.0;
+    const BAR_HEIGHT: f32 = 24.0;
+
+    let Some(snapshot) = &battle.snapshot else {
+        return;
+    };
+
+    let screen_width = screen_width();
+    let y = screen_height() * 0.25;
+    snapshot
+        .combatants
+        .iter()
+        .enumerate()
+        .for_each(|(idx, combatant)| {
+            let x = screen_width * (0.25 + 0.5 * idx as f32) - BAR_WIDTH * 0.5;
+
+            let name = if Some(combatant.id) == battle.own_id {
+                format!("{} (You)", combatant.name)
+            } else {
+                combatant.name.clone()
+            };
+            draw_text(&name, x, y - 16.0, 32.0, BLACK);
+
+            let fill = combatant.hp as f32 / combatant.max_hp.max(1) as f32;
+            draw_rectangle(x, y, BAR_WIDTH, BAR_HEIGHT, DARKGRAY);
+            draw_rectangle(x, y, BAR_WIDTH * fill, BAR_HEIGHT, GREEN);
+            if combatant.id == snapshot.turn && battle.outcome.is_none() {
+                draw_rectangle_lines(x, y, BAR_WIDTH, BAR_HEIGHT, 3.0, GOLD);
+            }
+            draw_text(
+                &format!("{}/{}", combatant.hp, combatant.max_hp),
+                x + 8.0,
+                y + BAR_HEIGHT - 6.0,
+                24.0,
+                BLACK,
+            );
+
+            let mut notes = Vec::new();
+            if combatant.defending {
+                notes.push("Defending");
+            }
+            if combatant.ability_used {
+                notes.push("Heavy strike used");
+            }
+            draw_text(&notes.join(", "), x, y + BAR_HEIGHT + 24.0, 24.0, MAROON);
+        });
+}
+
+#[system]
+fn draw_turn_log(#[resource] battle: &BattleState) {
+    let screen_height = screen_height();
+    battle.log.iter().rev().enumerate().for_each(|(idx, line)| {
+        let y = screen_height - idx as f32 * 32.0 - 64.0;
+
+        draw_text(line, 16.0, y, 24.0, BLACK);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use common::battle::CombatantSnapshot;
+
+    use super::*;
+
+    pub(super) const ALICE: NetworkID = NetworkID::new(1);
+    pub(super) const BOB: NetworkID = NetworkID::new(2);
+
+    pub(super) fn snapshot(turn: NetworkID) -> BattleSnapshot {
+        let combatant = |id, name: &str| CombatantSnapshot {
+            id,
+            name: name.to_string(),
+            hp: 100,
+            max_hp: 100,
+            defending: false,
+            ability_used: false,
+        };
+
+        BattleSnapshot {
+            combatants: [combatant(ALICE, "Alice"), combatant(BOB, "Bob")],
+            turn,
+            round: 1,
+        }
+    }
+
+    #[test]
+    fn test_actions_are_checked_locally() {
+        let mut battle = BattleState::default();
+        assert!(battle.check_action(BattleAction::Attack).is_err());
+
+        battle.start(snapshot(BOB), "alice");
+        assert_eq!(battle.own_id, Some(ALICE));
+        assert_eq!(
+            battle.check_action(BattleAction::Attack),
+            Err("It is not your turn.")
+        );
+        assert_eq!(battle.check_action(BattleAction::Forfeit), Ok(()));
+
+        let mut own_turn = snapshot(ALICE);
+        own_turn.combatants[0].ability_used = true;
+        battle.apply_turn(
+            TurnResult {
+                actor: BOB,
+                action: BattleAction::Defend,
+                damage: 0,
+                description: "Bob raises their guard.".to_string(),
+            },
+            own_turn,
+        );
+        assert_eq!(battle.check_action(BattleAction::Attack), Ok(()));
+        assert_eq!(
+            battle.check_action(BattleAction::Ability),
+            Err("You have already used your heavy strike.")
+        );
+    }
+
+    #[test]
+    fn test_outcome_is_described_from_our_side() {
+        let mut battle = BattleState::default();
+        battle.start(snapshot(ALICE), "Bob");
+        battle.end(BattleOutcome {
+            winner: ALICE,
+            loser: BOB,
+            reason: BattleEnd::Forfeited,
+        });
+
+        assert_eq!(battle.status(), "Defeat");
+        assert_eq!(
+            battle.log,
+            ["The duel has begun!", "You forfeited the duel."]
+        );
+    }
+
+    #[test]
+    fn test_spectators_can_only_watch() {
+        let mut battle = BattleState::default();
+        battle.start(snapshot(ALICE), "Carol");
+        battle.spectators = 2;
+
+        assert!(battle.is_spectating());
+        assert_eq!(
+            battle.check_action(BattleAction::Attack),
+            Err("You are only watching this duel.")
+        );
+        assert_eq!(
+            battle.status(),
+            "Round 1: Waiting for Alice... (2 watching)"
+        );
+
+        battle.end(BattleOutcome {
+            winner: ALICE,
+            loser: BOB,
+            reason: BattleEnd::Forfeited,
+        });
+        assert_eq!(battle.status(), "Alice wins! (2 watching)");
+        assert_eq!(
+            battle.log.back().map(String::as_str),
+            Some("Bob forfeited. Alice wins!")
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use client::{Client, ClientEvent, ConnectionInterface};
+use common::ClientMode;
+use legion::system;
+
+use crate::{AppState, DeferredEvents, NextState};
+
+use super::BattleState;
+
+#[system]
+pub fn handle_battle_events<T: ConnectionInterface + Send + Sync + 'static>(
+    #[resource] client: &mut Client<T>,
+    #[resource] battle: &mut BattleState,
+    #[resource] deferred_events: &mut DeferredEvents,
+    #[resource] next_state: &mut NextState,
+) {
+    client.receive_messages().expect("This should succeed.");
+    // The overworld may have received the start of the duel for us.
+    let deferred: Vec<ClientEvent> = deferred_events.0.drain(..).collect();
+    deferred
+        .into_iter()
+        .chain(client.get_event_receiver().try_iter())
+        .for_each(|event| match event {
+            ClientEvent::BattleStarted(snapshot) => {
+                let own_name = client.get_username().unwrap_or_default();
+                battle.start(snapshot, own_name);
+            }
+            ClientEvent::BattleTurn(result, snapshot) => battle.apply_turn(result, snapshot),
+            ClientEvent::BattleEnded(outcome) => battle.end(outcome),
+            ClientEvent::SpectatorsChanged(count) => battle.spectators = count,
+            // With an outcome to show, the player leaves when they are ready.
+            ClientEvent::ModeChanged(ClientMode::Overworld) if battle.outcome.is_none() => {
+                next_state.0 = Some(AppState::Overworld);
+            }
+            ClientEvent::ModeChanged(_) => {}
+            // Everything else happened in the overworld, which catches up
+            // on it once we are back.
+            event => deferred_events.defer(event),
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use client::test_utils::{TestClient, TestConnection};
+    use common::{
+        battle::{BattleAction, BattleEnd, BattleOutcome, TurnResult},
+        math::Vec2,
+        messages::{ChatChannel, ServerMessage},
+    };
+    use legion::{Resources, Schedule, World};
+
+    use super::*;
+    use crate::battle::tests::{snapshot, ALICE, BOB};
+
+    #[test]
+    fn test_server_messages_update_the_battle() {
+        let mut resources = Resources::default();
+        resources.insert(BattleState::default());
+        resources.insert(NextState(None));
+
+        // The overworld saw the duel start before handing over.
+        let mut start = snapshot(ALICE);
+        start.combatants[0].name = "TestUser".to_string();
+        let mut deferred_events = DeferredEvents::default();
+        deferred_events
+            .0
+            .push_back(ClientEvent::BattleStarted(start));
+        resources.insert(deferred_events);
+
+        let mut client = TestClient::already_connected();
+        let server = client.fake_server();
+        resources.insert(client);
+
+        let mut world = World::default();
+        let mut schedule = Schedule::builder()
+            .add_system(handle_battle_events_system::<TestConnection>())
+            .build();
+
+        let result = TurnResult {
+            actor: ALICE,
+            action: BattleAction::Attack,
+            damage: 12,
+            description: "Alice attacks Bob for 12 damage.".to_string(),
+        };
+        server.send_all([
+            ServerMessage::BattleTurn(result, snapshot(BOB)),
+            ServerMessage::notice("Bob has connected"),
+            ServerMessage::BattleEnded(BattleOutcome {
+                winner: ALICE,
+                loser: BOB,
+                reason: BattleEnd::Forfeited,
+            }),
+            ServerMessage::ChangeClientMode(ClientMode::Overworld),
+        ]);
+        schedule.execute(&mut world, &mut resources);
+
+        let battle = resources.get::<BattleState>().unwrap();
+        assert_eq!(battle.snapshot, Some(snapshot(BOB)));
+        assert_eq!(
+            battle.log.back().map(String::as_str),
+            Some("Bob forfeited. You win!")
+        );
+        // The player still has to see how the duel ended.
+        assert_eq!(resources.get::<NextState>().unwrap().0, None);
+        let notice_deferred = resources.get::<DeferredEvents>().unwrap().0.iter().any(
+            |event| matches!(event, ClientEvent::MessageReceived(_, _, text) if text == "Bob has connected"),
+        );
+        assert!(notice_deferred);
+    }
+
+    #[test]
+    fn test_long_duels_do_not_pile_up_movement() {
+        let mut resources = Resources::default();
+        resources.insert(BattleState::default());
+        resources.insert(NextState(None));
+        resources.insert(DeferredEvents::default());
+
+        let mut client = TestClient::already_connected();
+        let server = client.fake_server();
+        resources.insert(client);
+
+        let mut world = World::default();
+        let mut schedule = Schedule::builder()
+            .add_system(handle_battle_events_system::<TestConnection>())
+            .build();
+
+        // A few minutes of everyone else walking around.
+        (1..=10_000).for_each(|tick| {
+            let pos = Vec2::new(tick as f32, 0.0);
+            server.send_all([
+                ServerMessage::PositionSnapshot(tick, vec![(ALICE, pos), (BOB, pos)]),
+                ServerMessage::AcknowledgeMove(tick as u32, pos),
+            ]);
+            schedule.execute(&mut world, &mut resources);
+        });
+        server.send_all([ServerMessage::notice("Bob has connected")]);
+        schedule.execute(&mut world, &mut resources);
+
+        let latest = Vec2::new(10_000.0, 0.0);
+        let deferred = resources.get::<DeferredEvents>().unwrap();
+        assert_eq!(
+            deferred.0.iter().collect::<Vec<_>>(),
+            [
+                &ClientEvent::PositionSnapshot(10_000, vec![(ALICE, latest), (BOB, latest)]),
+                &ClientEvent::MoveAcknowledged(10_000, latest),
+                &ClientEvent::MessageReceived(
+                    ChatChannel::System,
+                    "SERVER".to_string(),
+                    "Bob has connected".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use common::battle::BattleAction;
+use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, Query};
+
+use crate::ui::{
+    spawner::{spawn_button, spawn_dynamic_text, spawn_spacer, spawn_ui_container, spawn_ui_panel},
+    Disabled, FullscreenRoot, Text, UIConstraint, UIContainer, UIRoot, UISize,
+};
+
+use super::{BattleState, BattleUIEvent, BattleUIEventChannel};
+
+/// The text at the top of the screen saying whose turn it is.
+pub struct BattleStatusText;
+
+/// The panel of action buttons, which is swapped for a single button
+/// back to the overworld once the duel is over.
+pub struct ActionPanel;
+
+/// Marks an action panel that has been set up for a spectator.
+pub struct SpectatorControls;
+
+#[system]
+pub fn spawn_battle_ui(
+    #[resource] ui_event_channel: &BattleUIEventChannel,
+    commands: &mut CommandBuffer,
+) {
+    let status_text = spawn_dynamic_text(commands, "");
+    commands.add_component(status_text, BattleStatusText);
+    commands.add_component(status_text, UIConstraint::width_constraint(512.0));
+
+    // The combatants are drawn over this space.
+    let spacer = spawn_spacer(commands);
+    commands.add_component(spacer, UISize::Grow(4));
+
+    let buttons: Vec<Entity> = [
+        BattleAction::Attack,
+        BattleAction::Defend,
+        BattleAction::Ability,
+        BattleAction::Forfeit,
+    ]
+    .iter()
+    .map(|action| {
+        spawn_button(
+            commands,
+            &action.to_string(),
+            ui_event_channel.0.clone(),
+            BattleUIEvent::Action(*action),
+        )
+    })
+    .collect();
+    let action_panel = spawn_ui_panel(commands, &buttons);
+    commands.add_component(action_panel, ActionPanel);
+    commands.add_component(action_panel, UISize::Grow(4));
+    commands.add_component(action_panel, UIConstraint::width_constraint(320.0));
+
+    let root = spawn_ui_container(commands, &[status_text, spacer, action_panel]);
+    commands.add_component(root, UIRoot);
+    commands.add_component(root, FullscreenRoot);
+}
+
+#[system(for_each)]
+pub fn update_battle_status(
+    _: &BattleStatusText,
+    text: &mut Text,
+    #[resource] battle: &BattleState,
+) {
+    text.0 = battle.status();
+}
+
+/// Spectators see the same actions as the fighters, but can only
+/// stop watching.
+#[system]
+#[read_component(UIContainer)]
+pub fn show_spectator_controls(
+    world: &mut SubWorld,
+    panel_query: &mut Query<(
+        Entity,
+        &ActionPanel,
+        &UIContainer,
+        Option<&SpectatorControls>,
+    )>,
+    #[resource] battle: &BattleState,
+    #[resource] ui_event_channel: &BattleUIEventChannel,
+    commands: &mut CommandBuffer,
+) {
+    if !battle.is_spectating() || battle.outcome.is_some() {
+        return;
+    }
+
+    panel_query
+        .iter(world)
+        .filter(|(_, _, _, controls)| controls.is_none())
+        .for_each(|(entity, _, container, _)| {
+            container
+                .children
+                .iter()
+                .for_each(|button| commands.add_component(*button, Disabled));
+
+            let stop_button = spawn_button(
+                commands,
+                "Stop Watching",
+                ui_event_channel.0.clone(),
+                BattleUIEvent::StopSpectating,
+            );
+            let mut children = container.children.clone();
+            children.push(stop_button);
+            commands.add_component(*entity, container.with_children(&children));
+            commands.add_component(*entity, SpectatorControls);
+        });
+}
+
+#[system]
+#[read_component(UIContainer)]
+pub fn show_return_button(
+    world: &mut SubWorld,
+    panel_query: &mut Query<(Entity, &ActionPanel, &UIContainer)>,
+    #[resource] battle: &BattleState,
+    #[resource] ui_event_channel: &BattleUIEventChannel,
+    commands: &mut CommandBuffer,
+) {
+    if battle.outcome.is_none() {
+        return;
+    }
+
+    panel_query.iter(world).for_each(|(entity, _, container)| {
+        UIContainer::recursive_delete_children(world, container, commands);
+
+        let return_button = spawn_button(
+            commands,
+            "Return",
+            ui_event_channel.0.clone(),
+            BattleUIEvent::ReturnToOverworld,
+        );
+        commands.add_component(*entity, container.with_children(&[return_button]));
+        commands.remove_component::<ActionPanel>(*entity);
+    });
+}
@@ -0,0 +1,106 @@
+use client::{functionality::DuelingClient, NetworkClient};
+use common::battle::BattleAction;
+use crossbeam_channel::{Receiver, Sender};
+use legion::system;
+
+use crate::NextState;
+
+use super::BattleState;
+
+pub struct BattleUIEventChannel(pub Sender<BattleUIEvent>, pub Receiver<BattleUIEvent>);
+
+#[derive(Copy, Clone)]
+pub enum BattleUIEvent {
+    Action(BattleAction),
+    StopSpectating,
+    ReturnToOverworld,
+}
+
+#[system]
+pub fn handle_battle_ui_events(
+    #[resource] ui_event_channel: &BattleUIEventChannel,
+    #[resource] client: &mut NetworkClient,
+    #[resource] battle: &mut BattleState,
+    #[resource] next_state: &mut NextState,
+) {
+    ui_event_channel
+        .1
+        .try_iter()
+        .for_each(|event| handle_event(&event, client, battle, next_state));
+}
+
+fn handle_event<T: DuelingClient>(
+    event: &BattleUIEvent,
+    client: &mut T,
+    battle: &mut BattleState,
+    next_state: &mut NextState,
+) {
+    match event {
+        BattleUIEvent::Action(action) => {
+            if let Err(reason) = battle.check_action(*action) {
+                battle.add_log_line(reason.to_string());
+                return;
+            }
+
+            if let Err(e) = client.send_battle_action(*action) {
+                log::error!("There was an error sending your battle action! {e:?}");
+            }
+        }
+        BattleUIEvent::StopSpectating => {
+            // We go back once the server has let us go.
+            if let Err(e) = client.stop_spectating() {
+                log::error!("There was an error leaving the duel! {e:?}");
+            }
+        }
+        BattleUIEvent::ReturnToOverworld => {
+            next_state.0 = Some(crate::AppState::Overworld);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use client::test_utils::TestClient;
+    use common::messages::ClientMessage;
+
+    use super::*;
+    use crate::battle::tests::{snapshot, ALICE, BOB};
+
+    #[test]
+    fn test_only_allowed_actions_are_sent() {
+        let mut client = TestClient::already_connected();
+        let mut next_state = NextState(None);
+        let mut battle = BattleState::default();
+        let mut start = snapshot(BOB);
+        start.combatants[0].name = "TestUser".to_string();
+        battle.start(start, "TestUser");
+
+        handle_event(
+            &BattleUIEvent::Action(BattleAction::Attack),
+            &mut client,
+            &mut battle,
+            &mut next_state,
+        );
+        handle_event(
+            &BattleUIEvent::Action(BattleAction::Forfeit),
+            &mut client,
+            &mut battle,
+            &mut next_state,
+        );
+
+        let sent_actions: Vec<ClientMessage> = client
+            .get_sent_messages()
+            .into_iter()
+            .filter(|msg| matches!(msg, ClientMessage::BattleAction(_)))
+            .collect();
+        assert_eq!(
+            sent_actions,
+            [ClientMessage::BattleAction(BattleAction::Forfeit)]
+        );
+        assert_eq!(
+            battle.log.back().map(String::as_str),
+            Some("It is not your turn.")
+        );
+        assert_eq!(battle.own_id, Some(ALICE));
+    }
+}
@@ -1,9 +1,12 @@
+mod battle;
 mod main_menu;
 mod overworld;
 mod ui;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
+use battle::battle_schedules;
+use client::ClientEvent;
 use legion::{system, Resources, Schedule, World};
 use macroquad::{
     prelude::{Color, RED},
@@ -40,6 +43,7 @@ impl Application {
         let mut states = HashMap::new();
         states.insert(AppState::MainMenu, main_menu_schedules());
         states.insert(AppState::Overworld, overworld_schedules());
+        states.insert(AppState::Battle, battle_schedules());
         Self {
             is_running: true,
             world,
@@ -52,7 +56,7 @@ impl Application {
     pub fn handle_input(&mut self) {}
 
     pub fn tick(&mut self) {
-        let mut run_enter_step = false;
+        let mut previous_state = None;
         let mut next_state = self.resources.get_mut::<NextState>().unwrap();
 
         if let Some(new_state) = next_state.0 {
@@ -61,14 +65,19 @@ impl Application {
                 return;
             }
             if new_state != self.current_state {
+                previous_state = Some(self.current_state);
                 self.current_state = new_state;
                 next_state.0 = None;
-                run_enter_step = true;
             }
         }
 
         drop(next_state);
 
+        let run_enter_step = previous_state.is_some();
+        if let Some(previous_state) = previous_state {
+            self.resources.insert(PreviousState(previous_state));
+        }
+
         let schedules = self
             .states
             .get_mut(&self.current_state)
@@ -102,12 +111,39 @@ enum AppState {
     Startup,
     MainMenu,
     Overworld,
-    //Battle,
+    Battle,
     Quit,
 }
 
 pub struct NextState(Option<AppState>);
 
+/// The state that was running before the current one was entered.
+pub struct PreviousState(AppState);
+
+/// Client events that arrived while another state was running, kept
+/// for the state that knows what to do with them.
+#[derive(Default)]
+pub struct DeferredEvents(VecDeque<ClientEvent>);
+
+impl DeferredEvents {
+    /// Hold on to an event for later. Position snapshots and move
+    /// acknowledgements are out of date as soon as a newer one
+    /// arrives, so only the latest of each is kept.
+    pub fn defer(&mut self, event: ClientEvent) {
+        self.0.retain(|queued| !supersedes(&event, queued));
+        self.0.push_back(event);
+    }
+}
+
+fn supersedes(newer: &ClientEvent, older: &ClientEvent) -> bool {
+    match (newer, older) {
+        // Snapshots hold the position of every entity, not just those that moved.
+        (ClientEvent::PositionSnapshot(_, _), ClientEvent::PositionSnapshot(_, _)) => true,
+        (ClientEvent::MoveAcknowledged(_, _), ClientEvent::MoveAcknowledged(_, _)) => true,
+        _ => false,
+    }
+}
+
 pub struct Schedules {
     enter_schedule: Schedule,
     tick_schedule: Schedule,
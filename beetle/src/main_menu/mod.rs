@@ -106,7 +106,12 @@ fn handle_main_menu_events(
 
                     let validity_check = validate_username(&text.0);
 
-                    if validity_check.is_ok() {
+                    if let Err(err) = validity_check {
+                        // Error Occurred...
+                        let err_msg = format!("{err}");
+                        error!("{err}");
+                        handler.send_notification(MainMenuNotification::Error(err_msg));
+                    } else {
                         // Log In
                         info!("Logging in with username: {}", text.0);
                         let connection_result = client.connect(&text.0);
@@ -125,11 +130,6 @@ fn handle_main_menu_events(
                                 handler.send_notification(MainMenuNotification::Error(err_msg));
                             }
                         }
-                    } else {
-                        // Error Occurred...
-                        let err_msg = format!("{}", validity_check.as_ref().unwrap_err());
-                        error!("{}", validity_check.unwrap_err());
-                        handler.send_notification(MainMenuNotification::Error(err_msg));
                     }
                 }
             }
@@ -141,6 +141,15 @@ fn handle_main_menu_events(
 fn init_main_menu_resources(commands: &mut CommandBuffer) {
     commands.exec_mut(move |_, resources| {
         let event_handler = MainMenuEventHandler::new();
+
+        // We may be back here because the server disconnected us.
+        let previous_status = resources
+            .get::<NetworkClient>()
+            .map(|client| client.connection_status());
+        if let Some(ConnectionStatus::Failed(reason)) = previous_status {
+            event_handler.send_notification(MainMenuNotification::Error(reason.to_string()));
+        }
+
         resources.insert(event_handler);
         resources.insert(ClearColor(DARKBLUE));
         resources.insert(NetworkClient::default());
@@ -153,18 +162,33 @@ fn join_server_when_connected(
     world: &mut SubWorld,
     #[resource] client: &mut NetworkClient,
     #[resource] next_state: &mut NextState,
+    #[resource] handler: &MainMenuEventHandler,
     commands: &mut CommandBuffer,
 ) {
     // We don't care at this point whether or not the client is connected.
     // So this result can safely be ignored.
     client.receive_messages().ok();
 
-    if let ConnectionStatus::Connected = client.connection_status() {
-        // FIXME: Clearing all entities here is a weird temporary measure.
-        query.iter(world).for_each(|e| {
-            commands.remove(*e);
-        });
+    match client.connection_status() {
+        ConnectionStatus::Connected => {
+            // FIXME: Clearing all entities here is a weird temporary measure.
+            query.iter(world).for_each(|e| {
+                commands.remove(*e);
+            });
 
-        next_state.0 = Some(crate::AppState::Overworld);
+            next_state.0 = Some(crate::AppState::Overworld);
+        }
+        ConnectionStatus::Failed(reason) => {
+            info!("The server refused the connection: {reason:?}");
+            query.iter(world).for_each(|e| {
+                commands.remove(*e);
+            });
+
+            // Start over with a fresh client so the player can try again.
+            *client = NetworkClient::default();
+            spawn_login_menu(commands, handler);
+            handler.send_notification(MainMenuNotification::Error(reason.to_string()));
+        }
+        _ => {}
     }
 }
@@ -105,11 +105,25 @@ pub fn spawn_main_menu(
     commands.add_component(button_panel, UISize::Grow(2));
     commands.add_component(button_panel, UIConstraint::width_constraint(600.0));
 
+    let notification_display = spawn_dynamic_text(commands, "");
+    commands.add_component(
+        notification_display,
+        NotificationDisplay(event_handler.notification_receiver()),
+    );
+    commands.add_component(notification_display, UISize::Grow(1));
+
     let button_spacer_2 = spawn_spacer(commands);
     commands.add_component(button_spacer_2, UISize::Grow(3));
 
-    let button_container =
-        spawn_ui_container(commands, &[button_spacer_1, button_panel, button_spacer_2]);
+    let button_container = spawn_ui_container(
+        commands,
+        &[
+            button_spacer_1,
+            notification_display,
+            button_panel,
+            button_spacer_2,
+        ],
+    );
     commands.add_component(button_container, UISize::Grow(5));
 
     let root = spawn_ui_container(commands, &[title_text, spacer, button_container]);
@@ -0,0 +1,329 @@
+use std::collections::VecDeque;
+
+use client::{functionality::DuelingClient, ClientError, NetworkClient};
+use common::{
+    commands::{parse_chat_input, ChatCommand, ChatInput, CommandSpec, COMMANDS},
+    messages::{ChatChannel, ChatHistoryEntry},
+    validation::usernames_match,
+    NetworkID,
+};
+use crossbeam_channel::{Receiver, Sender};
+use legion::{system, world::SubWorld, IntoQuery};
+use macroquad::{
+    prelude::{Color, BLACK, DARKBLUE, DARKGRAY, DARKGREEN, DARKPURPLE, GOLD, GRAY, MAROON},
+    text::draw_text,
+    window::screen_height,
+};
+
+use super::player::HoverName;
+
+pub struct ChatMessageChannel(pub Sender<String>, pub Receiver<String>);
+
+const MAX_DISPLAYED_MESSAGES: usize = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ChatStyle {
+    Global,
+    Say,
+    System,
+    Whisper,
+    Emote,
+    /// Anything said before we joined.
+    History,
+    /// Replies to commands that only this client sees.
+    Info,
+    /// Feedback that only exists on this client, such as a typo in a command.
+    LocalError,
+}
+
+impl ChatStyle {
+    fn color(&self) -> Color {
+        match self {
+            Self::Global => BLACK,
+            Self::Say => DARKGRAY,
+            Self::System => GOLD,
+            Self::Whisper => DARKPURPLE,
+            Self::Emote => DARKGREEN,
+            Self::History => GRAY,
+            Self::Info => DARKBLUE,
+            Self::LocalError => MAROON,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatLine {
+    pub text: String,
+    pub style: ChatStyle,
+}
+
+pub struct ChatMessages(pub VecDeque<ChatLine>);
+
+impl ChatMessages {
+    pub fn new() -> Self {
+        Self(VecDeque::new())
+    }
+
+    fn push(&mut self, text: String, style: ChatStyle) {
+        self.0.push_back(ChatLine { text, style });
+
+        while self.0.len() > MAX_DISPLAYED_MESSAGES {
+            self.0.pop_front();
+        }
+    }
+
+    pub fn add_message(&mut self, channel: ChatChannel, author: &str, text: &str) {
+        let style = match channel {
+            ChatChannel::Global => ChatStyle::Global,
+            ChatChannel::Say => ChatStyle::Say,
+            ChatChannel::System => ChatStyle::System,
+        };
+        self.push(channel_line(channel, author, text), style);
+    }
+
+    /// Messages from before we joined are all shown the same way, so
+    /// they are not mistaken for anything said just now.
+    pub fn add_history(&mut self, entries: &[ChatHistoryEntry]) {
+        entries.iter().for_each(|entry| {
+            let age = describe_age(entry.age_secs);
+            let line = channel_line(entry.channel, &entry.author, &entry.text);
+            self.push(format!("({age}) {line}"), ChatStyle::History);
+        });
+    }
+
+    /// Whispers read differently depending on which side of them we are on.
+    pub fn add_whisper(&mut self, author: &str, recipient: &str, text: &str, own_name: &str) {
+        let line = if author == own_name {
+            format!("[To {recipient}]: {text}")
+        } else {
+            format!("[From {author}]: {text}")
+        };
+        self.push(line, ChatStyle::Whisper);
+    }
+
+    pub fn add_emote(&mut self, author: &str, text: &str) {
+        self.push(format!("* {author} {text}"), ChatStyle::Emote);
+    }
+
+    pub fn add_info(&mut self, text: &str) {
+        self.push(text.to_string(), ChatStyle::Info);
+    }
+
+    pub fn add_local_error(&mut self, text: &str) {
+        self.push(text.to_string(), ChatStyle::LocalError);
+    }
+}
+
+fn channel_line(channel: ChatChannel, author: &str, text: &str) -> String {
+    match channel {
+        ChatChannel::Global => format!("[Global] {author}: {text}"),
+        ChatChannel::Say => format!("[Say] {author}: {text}"),
+        // Notices stand on their own, whoever the server claims sent them.
+        ChatChannel::System => format!("[Server] {text}"),
+    }
+}
+
+fn describe_age(seconds: u64) -> String {
+    match seconds {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        _ => format!("{}h ago", seconds / 3600),
+    }
+}
+
+/// Players whose messages are hidden from the chat log.
+#[derive(Default)]
+pub struct IgnoredPlayers(Vec<String>);
+
+impl IgnoredPlayers {
+    pub fn contains(&self, username: &str) -> bool {
+        self.0.iter().any(|name| usernames_match(name, username))
+    }
+
+    /// Ignore the player, or stop ignoring them if they already were.
+    /// Returns whether they are now ignored.
+    fn toggle(&mut self, username: &str) -> bool {
+        if self.contains(username) {
+            self.0.retain(|name| !usernames_match(name, username));
+            false
+        } else {
+            self.0.push(username.to_string());
+            true
+        }
+    }
+}
+
+fn help_text(topic: Option<&CommandSpec>) -> String {
+    match topic {
+        Some(command) => format!("{} - {}", command.usage, command.description),
+        None => {
+            let names: Vec<String> = COMMANDS
+                .iter()
+                .map(|command| format!("/{}", command.name))
+                .collect();
+            format!(
+                "Commands: {}. Type /help command for details.",
+                names.join(", ")
+            )
+        }
+    }
+}
+
+fn find_player_by_name(world: &SubWorld, username: &str) -> Option<NetworkID> {
+    <(&NetworkID, &HoverName)>::query()
+        .iter(world)
+        .find(|(_, hover_name)| usernames_match(&hover_name.name, username))
+        .map(|(id, _)| *id)
+}
+
+#[system]
+#[read_component(NetworkID)]
+#[read_component(HoverName)]
+pub fn handle_sending_messages(
+    world: &SubWorld,
+    #[resource] client: &mut NetworkClient,
+    #[resource] message_stream: &ChatMessageChannel,
+    #[resource] chat_messages: &mut ChatMessages,
+    #[resource] ignored_players: &mut IgnoredPlayers,
+) {
+    let r = message_stream.1.clone();
+    r.try_iter().for_each(|m| {
+        let command = match parse_chat_input(&m) {
+            Ok(ChatInput::Say(channel, text)) => {
+                let result = client.send_chat_message(channel, &text);
+                report_send_result(result, chat_messages);
+                return;
+            }
+            Ok(ChatInput::Command(command)) => command,
+            Err(err) => {
+                chat_messages.add_local_error(&err.to_string());
+                return;
+            }
+        };
+
+        let result = match command {
+            ChatCommand::Whisper { target, text } => client.send_whisper(&target, &text),
+            ChatCommand::Server(command) => client.send_command(command),
+            ChatCommand::Duel(target) => match find_player_by_name(world, &target) {
+                Some(id) => client.send_challenge(id),
+                None => {
+                    chat_messages.add_local_error(&format!("{target} is not nearby."));
+                    Ok(())
+                }
+            },
+            ChatCommand::CancelDuel(target) => match find_player_by_name(world, &target) {
+                Some(id) => client.cancel_challenge(id),
+                None => {
+                    chat_messages.add_local_error(&format!("{target} is not nearby."));
+                    Ok(())
+                }
+            },
+            ChatCommand::Ignore(target) => {
+                let text = if ignored_players.toggle(&target) {
+                    format!("You are now ignoring {target}.")
+                } else {
+                    format!("You are no longer ignoring {target}.")
+                };
+                chat_messages.add_info(&text);
+                Ok(())
+            }
+            ChatCommand::Help(topic) => {
+                chat_messages.add_info(&help_text(topic));
+                Ok(())
+            }
+        };
+
+        report_send_result(result, chat_messages);
+    });
+}
+
+/// Messages the client refused to send are the player's to fix, so
+/// they are shown in the chat log.
+fn report_send_result(result: Result<(), ClientError>, chat_messages: &mut ChatMessages) {
+    match result {
+        Err(ClientError::InvalidMessage(err)) => chat_messages.add_local_error(&err.to_string()),
+        result => result.expect("Just close your eyes and pretend it will always work out."),
+    }
+}
+
+#[system]
+pub fn draw_chatlog(#[resource] chatlog: &ChatMessages) {
+    let screen_height = screen_height();
+    chatlog.0.iter().rev().enumerate().for_each(|(idx, line)| {
+        let y = screen_height - idx as f32 * 32.0 - 64.0;
+
+        draw_text(&line.text, 16.0, y, 24.0, line.style.color());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_toggles() {
+        let mut ignored = IgnoredPlayers::default();
+
+        assert!(ignored.toggle("Bob"));
+        assert!(ignored.contains("bob"));
+        assert!(!ignored.toggle("BOB"));
+        assert!(!ignored.contains("Bob"));
+    }
+
+    #[test]
+    fn test_channels_are_prefixed() {
+        let mut chat = ChatMessages::new();
+        chat.add_message(ChatChannel::Global, "Alice", "hi");
+        chat.add_message(ChatChannel::Say, "Bob", "psst");
+        chat.add_message(ChatChannel::System, "SERVER", "Welcome!");
+
+        let lines: Vec<(&str, ChatStyle)> = chat
+            .0
+            .iter()
+            .map(|line| (line.text.as_str(), line.style))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                ("[Global] Alice: hi", ChatStyle::Global),
+                ("[Say] Bob: psst", ChatStyle::Say),
+                ("[Server] Welcome!", ChatStyle::System),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_history_is_marked() {
+        let mut chat = ChatMessages::new();
+        let entry = |age_secs, text: &str| ChatHistoryEntry {
+            age_secs,
+            channel: ChatChannel::Global,
+            author: "Alice".to_string(),
+            text: text.to_string(),
+        };
+        chat.add_history(&[entry(3600, "first"), entry(100, "second")]);
+
+        let lines: Vec<(&str, ChatStyle)> = chat
+            .0
+            .iter()
+            .map(|line| (line.text.as_str(), line.style))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                ("(1h ago) [Global] Alice: first", ChatStyle::History),
+                ("(1m ago) [Global] Alice: second", ChatStyle::History),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_whisper_direction() {
+        let mut chat = ChatMessages::new();
+        chat.add_whisper("Alice", "Bob", "hi", "Alice");
+        chat.add_whisper("Bob", "Alice", "hey", "Alice");
+
+        let lines: Vec<&str> = chat.0.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(lines, ["[To Bob]: hi", "[From Bob]: hey"]);
+    }
+}
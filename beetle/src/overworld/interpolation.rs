@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+
+use common::math::Vec2;
+
+const DEFAULT_DELAY_SECS: f64 = 0.1;
+const DEFAULT_MAX_EXTRAPOLATION_SECS: f64 = 0.25;
+const MAX_SAMPLES: usize = 32;
+/// How quickly the clock offset follows samples that arrive later than
+/// the quickest one so far.
+const CLOCK_DRIFT_RATE: f64 = 0.01;
+
+/// Settings used for every newly spawned remote entity.
+#[derive(Copy, Clone, Debug)]
+pub struct InterpolationSettings {
+    /// How far behind the newest sample an entity is rendered.
+    pub delay: f64,
+    /// How far past the newest sample an entity may be predicted
+    /// when updates stop arriving.
+    pub max_extrapolation: f64,
+}
+
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        Self {
+            delay: DEFAULT_DELAY_SECS,
+            max_extrapolation: DEFAULT_MAX_EXTRAPOLATION_SECS,
+        }
+    }
+}
+
+/// Buffers position samples of a remote entity, timed by the server
+/// tick they were taken on, so it can be drawn moving smoothly
+/// between network updates.
+pub struct Interpolation {
+    samples: VecDeque<(f64, Vec2)>,
+    /// How far the local clock is ahead of the server's.
+    clock_offset: Option<f64>,
+    settings: InterpolationSettings,
+}
+
+impl Interpolation {
+    pub fn new(settings: InterpolationSettings) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            clock_offset: None,
+            settings,
+        }
+    }
+
+    /// Add where the entity was at the given server time, which is
+    /// mapped onto the local clock using when the sample arrived.
+    pub fn push_sample(&mut self, server_time: f64, arrival_time: f64, pos: Vec2) {
+        if let Some((newest, _)) = self.samples.back() {
+            if server_time < *newest {
+                return;
+            }
+        }
+
+        // Samples held up on the way are just late, not evidence that the
+        // clocks are further apart. Only creep towards them, so a slow drift
+        // between the clocks is still followed.
+        let offset = arrival_time - server_time;
+        self.clock_offset = Some(match self.clock_offset {
+            Some(current) if offset > current => current + (offset - current) * CLOCK_DRIFT_RATE,
+            _ => offset,
+        });
+
+        self.samples.push_back((server_time, pos));
+
+        while self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The smoothed position at the given time, if any samples have
+    /// been received yet.
+    pub fn sample(&self, now: f64) -> Option<Vec2> {
+        let render_time = now - self.clock_offset.unwrap_or_default() - self.settings.delay;
+
+        let (first_time, first_pos) = *self.samples.front()?;
+        if render_time <= first_time {
+            return Some(first_pos);
+        }
+
+        let surrounding = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .find(|((_, _), (end_time, _))| render_time <= *end_time);
+
+        if let Some(((start_time, start_pos), (end_time, end_pos))) = surrounding {
+            let span = end_time - start_time;
+            if span <= 0.0 {
+                return Some(*end_pos);
+            }
+            let t = (render_time - start_time) / span;
+            return Some(start_pos.lerp(*end_pos, t as f32));
+        }
+
+        // We have run out of samples, so keep moving in the last known direction for a little while.
+        let (newest_time, newest_pos) = *self.samples.back()?;
+        if self.samples.len() < 2 {
+            return Some(newest_pos);
+        }
+        let (prev_time, prev_pos) = self.samples[self.samples.len() - 2];
+        let span = newest_time - prev_time;
+        if span <= 0.0 {
+            return Some(newest_pos);
+        }
+
+        let ahead = (render_time - newest_time).min(self.settings.max_extrapolation);
+        let velocity = (newest_pos - prev_pos) * (1.0 / span as f32);
+        Some(newest_pos + velocity * ahead as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_interpolation() -> Interpolation {
+        Interpolation::new(InterpolationSettings {
+            delay: 0.1,
+            max_extrapolation: 0.2,
+        })
+    }
+
+    #[test]
+    fn test_no_samples() {
+        let interpolation = test_interpolation();
+        assert_eq!(interpolation.sample(1.0), None);
+    }
+
+    #[test]
+    fn test_interpolates_between_samples() {
+        let mut interpolation = test_interpolation();
+        interpolation.push_sample(1.0, 1.0, Vec2::new(0.0, 0.0));
+        interpolation.push_sample(2.0, 2.0, Vec2::new(10.0, 20.0));
+
+        assert_eq!(interpolation.sample(1.6), Some(Vec2::new(5.0, 10.0)));
+    }
+
+    #[test]
+    fn test_extrapolation_is_limited() {
+        let mut interpolation = test_interpolation();
+        interpolation.push_sample(1.0, 1.0, Vec2::new(0.0, 0.0));
+        interpolation.push_sample(2.0, 2.0, Vec2::new(10.0, 0.0));
+
+        assert_eq!(interpolation.sample(2.2), Some(Vec2::new(11.0, 0.0)));
+        assert_eq!(interpolation.sample(10.0), Some(Vec2::new(12.0, 0.0)));
+    }
+
+    #[test]
+    fn test_late_samples_do_not_stutter() {
+        let mut interpolation = test_interpolation();
+        interpolation.push_sample(1.0, 1.0, Vec2::new(0.0, 0.0));
+        interpolation.push_sample(2.0, 2.4, Vec2::new(10.0, 0.0));
+        interpolation.push_sample(3.0, 3.0, Vec2::new(20.0, 0.0));
+
+        assert_eq!(interpolation.sample(1.6), Some(Vec2::new(5.0, 0.0)));
+        assert_eq!(interpolation.sample(2.6), Some(Vec2::new(15.0, 0.0)));
+    }
+
+    #[test]
+    fn test_samples_are_placed_on_the_local_clock() {
+        let mut interpolation = test_interpolation();
+        interpolation.push_sample(1.0, 101.0, Vec2::new(0.0, 0.0));
+        interpolation.push_sample(2.0, 102.0, Vec2::new(10.0, 0.0));
+
+        assert_eq!(interpolation.sample(101.6), Some(Vec2::new(5.0, 0.0)));
+    }
+
+    #[test]
+    fn test_stale_samples_are_ignored() {
+        let mut interpolation = test_interpolation();
+        interpolation.push_sample(2.0, 2.0, Vec2::new(10.0, 0.0));
+        interpolation.push_sample(1.0, 1.0, Vec2::new(0.0, 0.0));
+
+        assert_eq!(interpolation.sample(1.5), Some(Vec2::new(10.0, 0.0)));
+    }
+}
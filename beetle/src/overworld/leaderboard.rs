@@ -0,0 +1,101 @@
+use common::battle::{Leaderboard, LeaderboardEntry};
+use legion::{systems::CommandBuffer, Entity};
+use macroquad::{
+    math::Rect,
+    window::{screen_height, screen_width},
+};
+
+use crate::ui::spawner::{spawn_context_menu, spawn_dynamic_text};
+
+const PANEL_WIDTH: f32 = 420.0;
+const LINE_HEIGHT: f32 = 36.0;
+
+fn describe_entry(entry: &LeaderboardEntry) -> String {
+    format!(
+        "{}. {} - {} ({}W {}L)",
+        entry.rank, entry.name, entry.rating, entry.wins, entry.losses
+    )
+}
+
+/// Every line shown on the leaderboard panel, top to bottom.
+fn leaderboard_lines(leaderboard: &Leaderboard) -> Vec<String> {
+    let mut lines = vec!["Leaderboard".to_string()];
+
+    if leaderboard.top.is_empty() {
+        lines.push("Nobody has fought a duel yet.".to_string());
+    }
+    lines.extend(leaderboard.top.iter().map(describe_entry));
+
+    lines.push(match &leaderboard.own {
+        Some(own) => format!("You: {}", describe_entry(own)),
+        None => "Fight a duel to be ranked.".to_string(),
+    });
+    lines
+}
+
+/// Show the leaderboard in the middle of the screen until the next
+/// click.
+pub fn spawn_leaderboard_panel(commands: &mut CommandBuffer, leaderboard: &Leaderboard) -> Entity {
+    let lines: Vec<Entity> = leaderboard_lines(leaderboard)
+        .iter()
+        .map(|line| spawn_dynamic_text(commands, line))
+        .collect();
+
+    let height = lines.len() as f32 * LINE_HEIGHT;
+    let panel = spawn_context_menu(commands, &lines);
+    commands.add_component(
+        panel,
+        Rect::new(
+            (screen_width() - PANEL_WIDTH) * 0.5,
+            (screen_height() - height) * 0.5,
+            PANEL_WIDTH,
+            height,
+        ),
+    );
+    panel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(rank: u32, name: &str, rating: i32) -> LeaderboardEntry {
+        LeaderboardEntry {
+            rank,
+            name: name.to_string(),
+            rating,
+            wins: 1,
+            losses: 0,
+        }
+    }
+
+    #[test]
+    fn test_own_rank_is_shown_below_the_top_players() {
+        let leaderboard = Leaderboard {
+            top: vec![entry(1, "Alice", 1216)],
+            own: Some(entry(2, "Bob", 1184)),
+        };
+
+        assert_eq!(
+            leaderboard_lines(&leaderboard),
+            [
+                "Leaderboard",
+                "1. Alice - 1216 (1W 0L)",
+                "You: 2. Bob - 1184 (1W 0L)"
+            ]
+        );
+
+        let empty = Leaderboard {
+            top: Vec::new(),
+            own: None,
+        };
+        assert_eq!(
+            leaderboard_lines(&empty),
+            [
+                "Leaderboard",
+                "Nobody has fought a duel yet.",
+                "Fight a duel to be ranked."
+            ]
+        );
+    }
+}
@@ -1,36 +1,44 @@
+mod chat;
+mod interpolation;
+mod leaderboard;
 mod network_events;
 mod player;
+mod prediction;
 mod spawner;
 mod ui_events;
 
 use std::collections::{HashMap, VecDeque};
 
-use client::NetworkClient;
+use client::{Connection, ConnectionStatus, NetworkClient};
 use common::{
     math::{Rect, Vec2},
     messages::InfoRequestType,
     NetworkID, PLAY_AREA_SIZE,
 };
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::unbounded;
 use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, Query, Schedule};
 use macroquad::{
-    prelude::{Color, BLACK, DARKBROWN},
+    prelude::{Color, DARKBROWN},
     shapes::draw_rectangle,
-    text::draw_text,
     window::{screen_height, screen_width},
 };
 
 use crate::{
     draw_clear_color_system,
     ui::{
-        add_ui_layout_systems, add_ui_rendering_systems,
+        add_ui_layout_systems, add_ui_rendering_systems, despawn_ui_system,
         spawner::{spawn_button, spawn_dynamic_text, spawn_spacer, spawn_ui_container},
         UIContainer, UILayer,
     },
-    ClearColor, Schedules,
+    AppState, ClearColor, DeferredEvents, NextState, PreviousState, Schedules,
 };
 
 use self::{
+    chat::{
+        draw_chatlog_system, handle_sending_messages_system, ChatMessageChannel, ChatMessages,
+        IgnoredPlayers,
+    },
+    interpolation::InterpolationSettings,
     network_events::handle_client_events_system,
     player::{
         draw_hover_name_system, draw_world_objects_system, move_player_system,
@@ -40,8 +48,6 @@ use self::{
     ui_events::{handle_overworld_ui_events_system, OverworldUIEvent, OverworldUIEventChannel},
 };
 
-pub struct ChatMessageChannel(pub Sender<String>, pub Receiver<String>);
-
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum OverworldNotification {
     ReceivedChallenge(NetworkID),
@@ -53,6 +59,7 @@ pub struct OverworldNotifications(VecDeque<OverworldNotification>);
 pub fn overworld_schedules() -> Schedules {
     let enter_schedule = Schedule::builder()
         .add_system(initialize_overworld_resources_system())
+        .add_system(despawn_ui_system())
         .flush()
         .add_system(spawn_overworld_ui_system())
         .add_system(spawn_overworld_entities_system())
@@ -61,7 +68,8 @@ pub fn overworld_schedules() -> Schedules {
     let mut tick_sbuilder = Schedule::builder();
     add_ui_layout_systems::<OverworldUIEvent>(&mut tick_sbuilder);
     tick_sbuilder
-        .add_system(handle_client_events_system())
+        .add_system(handle_client_events_system::<Connection>())
+        .add_system(return_to_menu_when_disconnected_system())
         .add_system(handle_overworld_ui_events_system())
         .flush()
         .add_system(move_player_system())
@@ -88,27 +96,16 @@ pub fn overworld_schedules() -> Schedules {
     }
 }
 
+/// The main menu shows why the server disconnected us once we get there.
 #[system]
-fn handle_sending_messages(
-    #[resource] client: &mut NetworkClient,
-    #[resource] message_stream: &ChatMessageChannel,
+pub(crate) fn return_to_menu_when_disconnected(
+    #[resource] client: &NetworkClient,
+    #[resource] next_state: &mut NextState,
 ) {
-    let r = message_stream.1.clone();
-    r.try_iter().for_each(|m| {
-        client
-            .send_chat_message(&m)
-            .expect("Just close your eyes and pretend it will always work out.");
-    });
-}
-
-#[system]
-fn draw_chatlog(#[resource] chatlog: &ChatMessages) {
-    let screen_height = screen_height();
-    chatlog.0.iter().rev().enumerate().for_each(|(idx, s)| {
-        let y = screen_height - idx as f32 * 32.0 - 64.0;
-
-        draw_text(s, 16.0, y, 24.0, BLACK);
-    });
+    if let ConnectionStatus::Failed(reason) = client.connection_status() {
+        log::info!("Disconnected by the server: {reason:?}");
+        next_state.0 = Some(AppState::MainMenu);
+    }
 }
 
 #[system]
@@ -127,31 +124,24 @@ fn draw_play_area() {
     );
 }
 
-const MAX_DISPLAYED_MESSAGES: usize = 5;
-pub struct ChatMessages(VecDeque<String>);
-
-impl ChatMessages {
-    fn new() -> Self {
-        Self(VecDeque::new())
-    }
-
-    fn add_message(&mut self, author: &str, text: &str) {
-        let message = format!("{author}: {text}");
-        self.0.push_back(message);
-
-        while self.0.len() > MAX_DISPLAYED_MESSAGES {
-            self.0.pop_front();
-        }
-    }
-}
-
 #[system]
 fn initialize_overworld_resources(commands: &mut CommandBuffer) {
     commands.exec_mut(|_, resources| {
         let clear_color = ClearColor(DARKBROWN);
         resources.insert(clear_color);
+
+        // Coming back from a duel, everything we knew about the world
+        // before it is still right. Only the UI has to be rebuilt.
+        let previous_state = resources.get::<PreviousState>().map(|state| state.0);
+        if previous_state == Some(AppState::Battle) {
+            return;
+        }
+
+        resources.insert(DeferredEvents::default());
         resources.insert(NetworkedEntities(HashMap::new()));
+        resources.insert(InterpolationSettings::default());
         resources.insert(ChatMessages::new());
+        resources.insert(IgnoredPlayers::default());
 
         let (s, r) = unbounded();
         resources.insert(ChatMessageChannel(s, r));
@@ -170,11 +160,8 @@ pub struct NetworkedEntities(HashMap<NetworkID, Entity>);
 fn request_names(_: &NeedsName, id: &NetworkID, #[resource] client: &mut NetworkClient) {
     let result = client.request_id_info(*id, InfoRequestType::Identity);
 
-    if result.is_err() {
-        log::error!(
-            "Encountered an error requesting information on an entity. {:?}",
-            result.unwrap_err()
-        );
+    if let Err(err) = result {
+        log::error!("Encountered an error requesting information on an entity. {err:?}");
     }
 }
 
@@ -193,7 +180,7 @@ fn update_notification(
     notif_query
         .iter(world)
         .for_each(|(entity, root, container)| {
-            let current = notifications.0.get(0);
+            let current = notifications.0.front();
             if current.copied() != root.0 {
                 commands.add_component(*entity, NotificationUIRoot(current.copied()));
                 commands.remove_component::<UILayer>(*entity);
@@ -1,24 +1,46 @@
 use super::{
+    chat::IgnoredPlayers,
+    interpolation::{Interpolation, InterpolationSettings},
+    leaderboard::spawn_leaderboard_panel,
     player::{HoverName, NeedsName},
+    prediction::Prediction,
     spawner::{spawn_local_player, spawn_remote_player},
     ChatMessages, NetworkedEntities, OverworldNotifications, Position,
 };
-use client::{ClientEvent, NetworkClient};
-use common::{messages::InfoSendType, GameArchetype};
-use legion::{system, systems::CommandBuffer};
+use crate::{AppState, DeferredEvents, NextState};
+use client::{Client, ClientEvent, ConnectionInterface};
+use common::{
+    battle::ChallengeResult,
+    messages::{ChatChannel, InfoSendType},
+    ClientMode, GameArchetype, NetworkID,
+};
+use legion::{system, systems::CommandBuffer, world::SubWorld, EntityStore, IntoQuery};
+use macroquad::time::get_time;
 
 #[system]
-pub fn handle_client_events(
+#[write_component(Interpolation)]
+#[write_component(Position)]
+#[write_component(Prediction)]
+#[read_component(HoverName)]
+#[allow(clippy::too_many_arguments)]
+pub fn handle_client_events<T: ConnectionInterface + Send + Sync + 'static>(
+    world: &mut SubWorld,
     #[resource] networked_entities: &mut NetworkedEntities,
-    #[resource] client: &mut NetworkClient,
+    #[resource] interpolation_settings: &InterpolationSettings,
+    #[resource] client: &mut Client<T>,
     #[resource] chat_messages: &mut ChatMessages,
+    #[resource] ignored_players: &IgnoredPlayers,
     #[resource] notifications: &mut OverworldNotifications,
+    #[resource] next_state: &mut NextState,
+    #[resource] deferred_events: &mut DeferredEvents,
     commands: &mut CommandBuffer,
 ) {
     client.receive_messages().expect("This should succeed.");
-    client
-        .get_event_receiver()
-        .try_iter()
+    // Anything that happened in the world during a duel comes first.
+    let deferred: Vec<ClientEvent> = deferred_events.0.drain(..).collect();
+    deferred
+        .into_iter()
+        .chain(client.get_event_receiver().try_iter())
         .for_each(|event| match event {
             ClientEvent::SpawnEntity(id, entity_type, is_owned) => {
                 if let Some(existing) = networked_entities.0.get(&id) {
@@ -30,7 +52,7 @@ pub fn handle_client_events(
                         if is_owned {
                             spawn_local_player(commands)
                         } else {
-                            spawn_remote_player(commands)
+                            spawn_remote_player(commands, *interpolation_settings)
                         }
                     }
                 };
@@ -39,7 +61,7 @@ pub fn handle_client_events(
                 networked_entities.0.insert(id, e);
             }
             ClientEvent::DespawnEntity(id) => {
-                if let Some(e)  = networked_entities.0.remove(&id) {
+                if let Some(e) = networked_entities.0.remove(&id) {
                     commands.remove(e);
                 }
                 // We don't mind if this silently passes if the entity wasn't spawned in.
@@ -52,24 +74,297 @@ pub fn handle_client_events(
                         }
                         InfoSendType::Identity(name) => {
                             commands.add_component(*e, HoverName { name, radius: 24.0 });
-                            commands.remove_component::<NeedsName>(*e);}
+                            commands.remove_component::<NeedsName>(*e);
+                        }
                     }
                 } else {
-                    log::info!(
-                        "Did not have an entity to update with given info. Requesting archetype from server..."
-                    );
-                    // FIXME: Do not pretend there are never network issues.
-                    client
-                        .request_id_archetype(id)
-                        .expect("We just pretend there are never network issues.");
+                    request_missing_entity(client, id);
                 }
             }
-            ClientEvent::MessageReceived(author, text) => {
+            ClientEvent::PositionSnapshot(tick, positions) => {
+                let Some(tick_duration) = client.server_tick_duration() else {
+                    return;
+                };
+                let server_time = tick as f64 * tick_duration;
+
+                positions.into_iter().for_each(|(id, pos)| {
+                    let Some(e) = networked_entities.0.get(&id) else {
+                        request_missing_entity(client, id);
+                        return;
+                    };
+
+                    // Remote entities spawned this tick only get their buffer next tick,
+                    // until then they are simply drawn at their latest position.
+                    if let Ok(mut entry) = world.entry_mut(*e) {
+                        if let Ok(interpolation) = entry.get_component_mut::<Interpolation>() {
+                            interpolation.push_sample(server_time, get_time(), pos);
+                        }
+                    }
+                    commands.add_component(*e, Position(pos));
+                });
+            }
+            ClientEvent::MoveAcknowledged(sequence, authoritative) => {
+                let mut query = <(&mut Position, &mut Prediction)>::query();
+                query.iter_mut(world).for_each(|(pos, prediction)| {
+                    pos.0 = prediction.reconcile(pos.0, sequence, authoritative);
+                });
+            }
+            ClientEvent::MessageReceived(ChatChannel::Global | ChatChannel::Say, author, _)
+            | ClientEvent::Emote(author, _)
+            | ClientEvent::Whisper { author, .. }
+                if ignored_players.contains(&author) => {}
+            ClientEvent::MessageReceived(channel, author, text) => {
                 log::info!("Received Message: {text} from {author}");
-                chat_messages.add_message(&author, &text);
+                chat_messages.add_message(channel, &author, &text);
+            }
+            ClientEvent::Whisper {
+                author,
+                recipient,
+                text,
+            } => {
+                let own_name = client.get_username().unwrap_or_default();
+                chat_messages.add_whisper(&author, &recipient, &text, own_name);
+            }
+            ClientEvent::Emote(author, text) => chat_messages.add_emote(&author, &text),
+            ClientEvent::ChatHistory(mut entries) => {
+                entries.retain(|entry| {
+                    entry.channel == ChatChannel::System || !ignored_players.contains(&entry.author)
+                });
+                chat_messages.add_history(&entries);
+            }
+            ClientEvent::ModeChanged(ClientMode::Battle | ClientMode::Spectating) => {
+                next_state.0 = Some(AppState::Battle);
+            }
+            ClientEvent::ModeChanged(ClientMode::Overworld) => {}
+            // The battle screen handles these once it has been entered.
+            ClientEvent::BattleStarted(_)
+            | ClientEvent::BattleTurn(_, _)
+            | ClientEvent::BattleEnded(_)
+            | ClientEvent::SpectatorsChanged(_) => deferred_events.0.push_back(event),
+            ClientEvent::Leaderboard(leaderboard) => {
+                spawn_leaderboard_panel(commands, &leaderboard);
             }
             ClientEvent::ChallengeReceived(sender) => {
-                notifications.0.push_back(super::OverworldNotification::ReceivedChallenge(sender));
+                notifications
+                    .0
+                    .push_back(super::OverworldNotification::ReceivedChallenge(sender));
+            }
+            ClientEvent::ChallengeResolved {
+                challenger,
+                target,
+                result,
+            } => {
+                let sent_by_us = client.get_player_id() == Some(challenger);
+                if !sent_by_us {
+                    // There is nothing left to answer.
+                    notifications.0.retain(|notification| {
+                        *notification != super::OverworldNotification::ReceivedChallenge(challenger)
+                    });
+                }
+
+                let other = if sent_by_us { target } else { challenger };
+                let name = player_name(world, networked_entities, other);
+                if let Some(text) = describe_challenge_result(result, &name, sent_by_us) {
+                    chat_messages.add_info(&text);
+                }
             }
         });
 }
+
+fn player_name(world: &SubWorld, networked_entities: &NetworkedEntities, id: NetworkID) -> String {
+    networked_entities
+        .0
+        .get(&id)
+        .and_then(|e| world.entry_ref(*e).ok())
+        .and_then(|entry| {
+            entry
+                .get_component::<HoverName>()
+                .ok()
+                .map(|name| name.name.clone())
+        })
+        .unwrap_or_else(|| "Someone".to_string())
+}
+
+/// What to tell the player about a challenge that is no longer
+/// pending, if anything. Answering a challenge needs no reply.
+fn describe_challenge_result(
+    result: ChallengeResult,
+    other_name: &str,
+    sent_by_us: bool,
+) -> Option<String> {
+    let text = match (result, sent_by_us) {
+        (ChallengeResult::Accepted, true) => format!("{other_name} accepted your challenge!"),
+        (ChallengeResult::Declined, true) => format!("{other_name} declined your challenge."),
+        (ChallengeResult::Expired, true) => {
+            format!("Your challenge to {other_name} went unanswered.")
+        }
+        (ChallengeResult::Cancelled, true) => {
+            format!("Your challenge to {other_name} was called off.")
+        }
+        (ChallengeResult::Expired, false) => {
+            format!("The challenge from {other_name} has expired.")
+        }
+        (ChallengeResult::Cancelled, false) => {
+            format!("The challenge from {other_name} was called off.")
+        }
+        (ChallengeResult::Accepted | ChallengeResult::Declined, false) => return None,
+    };
+    Some(text)
+}
+
+fn request_missing_entity<T: ConnectionInterface>(client: &mut Client<T>, id: NetworkID) {
+    log::info!(
+        "Did not have an entity to update with given info. Requesting archetype from server..."
+    );
+    // FIXME: Do not pretend there are never network issues.
+    client
+        .request_id_archetype(id)
+        .expect("We just pretend there are never network issues.");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use client::test_utils::{TestClient, TestConnection};
+    use common::{messages::ServerMessage, NetworkID};
+    use legion::{Resources, Schedule, World};
+
+    use super::*;
+    use crate::overworld::OverworldNotification;
+
+    fn test_resources() -> (Resources, TestClient) {
+        let mut resources = Resources::default();
+        resources.insert(NetworkedEntities(HashMap::new()));
+        resources.insert(InterpolationSettings::default());
+        resources.insert(ChatMessages::new());
+        resources.insert(IgnoredPlayers::default());
+        resources.insert(OverworldNotifications::default());
+        resources.insert(NextState(None));
+        resources.insert(DeferredEvents::default());
+
+        let client = TestClient::already_connected();
+        (resources, client)
+    }
+
+    #[test]
+    fn test_server_messages_update_the_overworld() {
+        let (mut resources, mut client) = test_resources();
+        let server = client.fake_server();
+        resources.insert(client);
+
+        let mut world = World::default();
+        let mut schedule = Schedule::builder()
+            .add_system(handle_client_events_system::<TestConnection>())
+            .build();
+
+        let other = NetworkID::new(1);
+        server.send_all([
+            ServerMessage::SpawnNetworkedEntity(other, GameArchetype::Player, false),
+            ServerMessage::SendMessage(ChatChannel::Say, "Other".to_string(), "Hello!".to_string()),
+            ServerMessage::PassAlongChallenge(other),
+        ]);
+        schedule.execute(&mut world, &mut resources);
+
+        assert!(resources
+            .get::<NetworkedEntities>()
+            .unwrap()
+            .0
+            .contains_key(&other));
+        assert_eq!(
+            resources
+                .get::<ChatMessages>()
+                .unwrap()
+                .0
+                .back()
+                .unwrap()
+                .text,
+            "[Say] Other: Hello!"
+        );
+        assert_eq!(
+            resources.get::<OverworldNotifications>().unwrap().0.front(),
+            Some(&OverworldNotification::ReceivedChallenge(other))
+        );
+
+        server.send(ServerMessage::DespawnNetworkedEntity(other));
+        schedule.execute(&mut world, &mut resources);
+
+        assert!(resources.get::<NetworkedEntities>().unwrap().0.is_empty());
+        assert_eq!(world.len(), 0);
+    }
+
+    #[test]
+    fn test_withdrawn_challenges_are_forgotten() {
+        let (mut resources, mut client) = test_resources();
+        let server = client.fake_server();
+        resources.insert(client);
+
+        let mut world = World::default();
+        let mut schedule = Schedule::builder()
+            .add_system(handle_client_events_system::<TestConnection>())
+            .build();
+
+        let challenger = NetworkID::new(1);
+        server.send(ServerMessage::PassAlongChallenge(challenger));
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(
+            resources.get::<OverworldNotifications>().unwrap().0.len(),
+            1
+        );
+
+        server.send(ServerMessage::ChallengeResolved {
+            challenger,
+            target: NetworkID::new(2),
+            result: ChallengeResult::Cancelled,
+        });
+        schedule.execute(&mut world, &mut resources);
+
+        assert!(resources
+            .get::<OverworldNotifications>()
+            .unwrap()
+            .0
+            .is_empty());
+        assert_eq!(
+            resources
+                .get::<ChatMessages>()
+                .unwrap()
+                .0
+                .back()
+                .unwrap()
+                .text,
+            "The challenge from Someone was called off."
+        );
+    }
+
+    #[test]
+    fn test_duels_hand_over_to_the_battle_screen() {
+        let (mut resources, mut client) = test_resources();
+        let server = client.fake_server();
+        resources.insert(client);
+
+        let mut world = World::default();
+        let mut schedule = Schedule::builder()
+            .add_system(handle_client_events_system::<TestConnection>())
+            .build();
+
+        let outcome = common::battle::BattleOutcome {
+            winner: NetworkID::new(1),
+            loser: NetworkID::new(2),
+            reason: common::battle::BattleEnd::Forfeited,
+        };
+        server.send_all([
+            ServerMessage::ChangeClientMode(ClientMode::Battle),
+            ServerMessage::BattleEnded(outcome),
+        ]);
+        schedule.execute(&mut world, &mut resources);
+
+        assert_eq!(
+            resources.get::<NextState>().unwrap().0,
+            Some(AppState::Battle)
+        );
+        assert_eq!(
+            resources.get::<DeferredEvents>().unwrap().0,
+            [ClientEvent::BattleEnded(outcome)]
+        );
+    }
+}
@@ -1,18 +1,23 @@
 use client::NetworkClient;
 use common::{
     math::{Rect, Vec2},
+    movement::{apply_movement, InputClock},
     NetworkID, PLAY_AREA_SIZE,
 };
 use legion::{system, systems::CommandBuffer};
 use macroquad::{
     prelude::{is_key_down, is_mouse_button_pressed, mouse_position, Color, WHITE},
     text::{draw_text, measure_text},
+    time::{get_frame_time, get_time},
     window::{screen_height, screen_width},
 };
 
 use crate::ui::spawner::{spawn_button, spawn_context_menu};
 
-use super::{OverworldUIEvent, OverworldUIEventChannel, Position};
+use super::{
+    interpolation::Interpolation, prediction::Prediction, OverworldUIEvent,
+    OverworldUIEventChannel, Position,
+};
 
 pub struct Player;
 pub struct Controller;
@@ -24,14 +29,20 @@ pub struct HoverName {
 }
 
 #[system(for_each)]
-pub fn draw_world_objects(display: &WorldDisplay, pos: &Position) {
+pub fn draw_world_objects(
+    display: &WorldDisplay,
+    pos: &Position,
+    interpolation: Option<&Interpolation>,
+    prediction: Option<&Prediction>,
+) {
     let screen_width = screen_width();
     let screen_height = screen_height();
     let tl = Vec2::new(screen_width * 0.5, screen_height * 0.5) - PLAY_AREA_SIZE * 0.5;
+    let pos = display_position(pos, interpolation, prediction, get_time());
     draw_text(
         &display.0,
-        tl.x + pos.0.x - 16.0,
-        tl.y + pos.0.y + 16.0,
+        tl.x + pos.x - 16.0,
+        tl.y + pos.y + 16.0,
         64.0,
         display.1,
     );
@@ -43,7 +54,11 @@ pub fn move_player(
     _: &Player,
     _: &Controller,
     pos: &mut Position,
+    prediction: &mut Prediction,
+    clock: &mut InputClock,
 ) {
+    prediction.decay_correction();
+
     let x_dir = match (
         is_key_down(macroquad::prelude::KeyCode::A),
         is_key_down(macroquad::prelude::KeyCode::D),
@@ -62,18 +77,41 @@ pub fn move_player(
         _ => 0.0,
     };
 
-    const PLAYER_SPEED: f32 = 4.0;
-    let move_vec = Vec2::new(x_dir, y_dir) * PLAYER_SPEED;
+    let direction = Vec2::new(x_dir, y_dir);
 
-    if move_vec != Vec2::ZERO {
-        pos.0 += move_vec;
+    if direction == Vec2::ZERO {
+        // Standing still should not bank time towards the next input.
+        *clock = InputClock::default();
+        return;
+    }
 
+    (0..clock.tick(get_frame_time() as f64)).for_each(|_| {
         // TODO: Handle network errors.
-        let result = client.move_player(pos.0);
-
-        if result.is_err() {
-            log::error!("Error sending move packet. {:?}", result.unwrap_err());
+        match client.move_player(direction) {
+            Ok(input) => {
+                pos.0 = apply_movement(pos.0, direction);
+                prediction.record(input);
+            }
+            Err(err) => log::error!("Error sending move packet. {err:?}"),
         }
+    });
+}
+
+/// The position an entity should be drawn at, smoothing over network
+/// updates for both remote and local players.
+pub fn display_position(
+    pos: &Position,
+    interpolation: Option<&Interpolation>,
+    prediction: Option<&Prediction>,
+    now: f64,
+) -> Vec2 {
+    let pos = interpolation
+        .and_then(|interpolation| interpolation.sample(now))
+        .unwrap_or(pos.0);
+
+    match prediction {
+        Some(prediction) => pos + prediction.correction(),
+        None => pos,
     }
 }
 
@@ -82,10 +120,15 @@ pub fn world_to_screen(pos: Vec2) -> Vec2 {
 }
 
 #[system(for_each)]
-pub fn draw_hover_name(pos: &Position, hover_name: &HoverName) {
+pub fn draw_hover_name(
+    pos: &Position,
+    interpolation: Option<&Interpolation>,
+    prediction: Option<&Prediction>,
+    hover_name: &HoverName,
+) {
     let mouse_pos: Vec2 = mouse_position().into();
 
-    let screen_pos = world_to_screen(pos.0);
+    let screen_pos = world_to_screen(display_position(pos, interpolation, prediction, get_time()));
 
     if screen_pos.distance_to(mouse_pos) <= hover_name.radius {
         let text_size = measure_text(&hover_name.name, None, 24, 1.0);
@@ -105,12 +148,13 @@ pub struct OtherPlayer;
 pub fn spawn_context_menu_when_rclicked(
     network_id: &NetworkID,
     pos: &Position,
+    interpolation: Option<&Interpolation>,
     _: &OtherPlayer,
     #[resource] event_stream: &OverworldUIEventChannel,
     commands: &mut CommandBuffer,
 ) {
     const CLICK_RADIUS: f32 = 32.0;
-    let screen_pos = world_to_screen(pos.0);
+    let screen_pos = world_to_screen(display_position(pos, interpolation, None, get_time()));
     let mouse_pos = mouse_position().into();
 
     if screen_pos.distance_to(mouse_pos) <= CLICK_RADIUS
@@ -122,7 +166,13 @@ pub fn spawn_context_menu_when_rclicked(
             event_stream.0.clone(),
             OverworldUIEvent::Challenge(*network_id),
         );
-        let menu = spawn_context_menu(commands, &[duel_button]);
+        let watch_button = spawn_button(
+            commands,
+            "WATCH",
+            event_stream.0.clone(),
+            OverworldUIEvent::Spectate(*network_id),
+        );
+        let menu = spawn_context_menu(commands, &[duel_button, watch_button]);
         commands.add_component(menu, Rect::new(mouse_pos.x, mouse_pos.y, 100.0, 200.0));
     }
 }
@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+
+use common::{
+    math::Vec2,
+    movement::{apply_movement, MoveInput},
+};
+
+/// How much of the remaining correction is kept each tick.
+const CORRECTION_DECAY: f32 = 0.85;
+const CORRECTION_SNAP_DISTANCE: f32 = 0.1;
+
+/// Tracks the movement inputs of the local player the server has
+/// not acknowledged yet, so they can be replayed on top of the
+/// authoritative position.
+#[derive(Default)]
+pub struct Prediction {
+    pending: VecDeque<MoveInput>,
+    last_acknowledged: Option<u32>,
+    correction: Vec2,
+}
+
+impl Prediction {
+    pub fn record(&mut self, input: MoveInput) {
+        self.pending.push_back(input);
+    }
+
+    /// Rebuild the predicted position from an acknowledged server
+    /// state. Any difference from the current prediction is kept as a
+    /// visual correction that fades out instead of snapping.
+    pub fn reconcile(&mut self, current: Vec2, sequence: u32, authoritative: Vec2) -> Vec2 {
+        if matches!(self.last_acknowledged, Some(last) if sequence <= last) {
+            return current;
+        }
+        self.last_acknowledged = Some(sequence);
+
+        self.pending.retain(|input| input.sequence > sequence);
+        let predicted = self.pending.iter().fold(authoritative, |pos, input| {
+            apply_movement(pos, input.direction)
+        });
+
+        self.correction += current - predicted;
+        predicted
+    }
+
+    pub fn decay_correction(&mut self) {
+        self.correction = self.correction * CORRECTION_DECAY;
+
+        if self.correction.length() < CORRECTION_SNAP_DISTANCE.powi(2) {
+            self.correction = Vec2::ZERO;
+        }
+    }
+
+    pub fn correction(&self) -> Vec2 {
+        self.correction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::movement::PLAYER_SPEED;
+
+    use super::*;
+
+    fn input(sequence: u32, x: f32) -> MoveInput {
+        MoveInput {
+            sequence,
+            direction: Vec2::new(x, 0.0),
+        }
+    }
+
+    #[test]
+    fn test_replays_unacknowledged_inputs() {
+        let mut prediction = Prediction::default();
+        let start = Vec2::new(100.0, 100.0);
+        let mut pos = start;
+        (0..3).for_each(|sequence| {
+            let input = input(sequence, 1.0);
+            pos = apply_movement(pos, input.direction);
+            prediction.record(input);
+        });
+
+        let after_first = Vec2::new(start.x + PLAYER_SPEED, start.y);
+        let result = prediction.reconcile(pos, 0, after_first);
+
+        assert_eq!(result, pos);
+        assert_eq!(prediction.correction(), Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_server_correction_is_smoothed() {
+        let mut prediction = Prediction::default();
+        let pos = Vec2::new(100.0, 100.0);
+        prediction.record(input(0, 1.0));
+
+        let result = prediction.reconcile(pos, 0, Vec2::new(90.0, 100.0));
+
+        assert_eq!(result, Vec2::new(90.0, 100.0));
+        assert_eq!(prediction.correction(), Vec2::new(10.0, 0.0));
+
+        (0..100).for_each(|_| prediction.decay_correction());
+        assert_eq!(prediction.correction(), Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_stale_acknowledgements_are_ignored() {
+        let mut prediction = Prediction::default();
+        let pos = Vec2::new(100.0, 100.0);
+
+        prediction.reconcile(pos, 5, pos);
+        let result = prediction.reconcile(pos, 3, Vec2::ZERO);
+
+        assert_eq!(result, pos);
+    }
+}
@@ -1,5 +1,5 @@
 use client::NetworkClient;
-use common::PLAY_AREA_SIZE;
+use common::{movement::InputClock, PLAY_AREA_SIZE};
 use legion::{system, systems::CommandBuffer, Entity};
 use macroquad::prelude::{GREEN, WHITE};
 
@@ -11,7 +11,9 @@ use crate::ui::{
 };
 
 use super::{
+    interpolation::{Interpolation, InterpolationSettings},
     player::{Controller, HoverName, NeedsName, OtherPlayer, Player, WorldDisplay},
+    prediction::Prediction,
     ChatMessageChannel, NotificationUIRoot, OverworldUIEvent, OverworldUIEventChannel, Position,
 };
 
@@ -28,6 +30,12 @@ pub fn spawn_overworld_ui(
     let top_text = spawn_dynamic_text(commands, &format!("Welcome to Shackle, {username}"));
     let button_spacer = spawn_spacer(commands);
     commands.add_component(button_spacer, UISize::Constant(32.0));
+    let leaderboard_button = spawn_button(
+        commands,
+        "Leaderboard",
+        ui_event_channel.0.clone(),
+        OverworldUIEvent::ShowLeaderboard,
+    );
     let logout_button = spawn_button(
         commands,
         "Log Out",
@@ -47,7 +55,14 @@ pub fn spawn_overworld_ui(
 
     let root_container = spawn_ui_container(
         commands,
-        &[top_text, button_spacer, logout_button, spacer, chat_input],
+        &[
+            top_text,
+            button_spacer,
+            leaderboard_button,
+            logout_button,
+            spacer,
+            chat_input,
+        ],
     );
     commands.add_component(root_container, UIRoot);
     commands.add_component(root_container, FullscreenRoot);
@@ -65,6 +80,8 @@ pub fn spawn_local_player(commands: &mut CommandBuffer) -> Entity {
     let pos = PLAY_AREA_SIZE * 0.5;
     commands.push((
         Position(pos),
+        Prediction::default(),
+        InputClock::default(),
         Player,
         Controller,
         WorldDisplay("@".to_string(), WHITE),
@@ -75,10 +92,14 @@ pub fn spawn_local_player(commands: &mut CommandBuffer) -> Entity {
     ))
 }
 
-pub fn spawn_remote_player(commands: &mut CommandBuffer) -> Entity {
+pub fn spawn_remote_player(
+    commands: &mut CommandBuffer,
+    interpolation_settings: InterpolationSettings,
+) -> Entity {
     let pos = PLAY_AREA_SIZE * 0.5;
     commands.push((
         Position(pos),
+        Interpolation::new(interpolation_settings),
         Player,
         WorldDisplay("@".to_string(), GREEN),
         NeedsName,
@@ -13,6 +13,9 @@ pub struct OverworldUIEventChannel(pub Sender<OverworldUIEvent>, pub Receiver<Ov
 pub enum OverworldUIEvent {
     Challenge(NetworkID),
     ChallengeResponse(NetworkID, bool),
+    /// Watch the duel the given player is fighting.
+    Spectate(NetworkID),
+    ShowLeaderboard,
     Logout,
 }
 
@@ -52,6 +55,17 @@ fn handle_event<T: DuelingClient>(
 
             notifications.0.pop_front();
         }
+        OverworldUIEvent::Spectate(id) => {
+            if let Err(e) = client.spectate_duel(*id) {
+                log::error!("There was an error asking to watch a duel! {e:?}");
+            }
+        }
+        OverworldUIEvent::ShowLeaderboard => {
+            // The panel is opened once the answer arrives.
+            if let Err(e) = client.request_leaderboard() {
+                log::error!("There was an error requesting the leaderboard! {e:?}");
+            }
+        }
         OverworldUIEvent::Logout => {
             next_state.0 = Some(crate::AppState::MainMenu);
         }
@@ -102,7 +116,7 @@ mod tests {
 
         let target = NetworkID::new(1);
         handle_event(
-            &OverworldUIEvent::Challenge(target.clone()),
+            &OverworldUIEvent::Challenge(target),
             &mut client,
             &mut next_state,
             &mut notifications,
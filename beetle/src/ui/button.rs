@@ -1,9 +1,10 @@
 use common::math::Rect;
 use crossbeam_channel::Sender;
-use legion::system;
+use legion::{component, system};
 use macroquad::{
     prelude::{
-        is_mouse_button_down, is_mouse_button_pressed, mouse_position, DARKGRAY, GRAY, LIGHTGRAY,
+        is_mouse_button_down, is_mouse_button_pressed, mouse_position, Color, DARKGRAY, GRAY,
+        LIGHTGRAY,
     },
     shapes::draw_rectangle,
 };
@@ -24,6 +25,9 @@ impl<T: Send + Sync + Copy> Button<T> {
     }
 }
 
+/// Marks a button that is shown but cannot be pressed.
+pub struct Disabled;
+
 #[derive(PartialEq)]
 enum ButtonState {
     Normal,
@@ -32,6 +36,7 @@ enum ButtonState {
 }
 
 #[system(for_each)]
+#[filter(!component::<Disabled>())]
 pub fn handle_button_input<T: Send + Sync + Copy + 'static>(button: &mut Button<T>, rect: &Rect) {
     let mouse_pos = mouse_position();
 
@@ -55,8 +60,13 @@ pub fn handle_button_input<T: Send + Sync + Copy + 'static>(button: &mut Button<
 }
 
 #[system(for_each)]
-pub fn draw_button<T: Send + Sync + Copy + 'static>(button: &Button<T>, rect: &Rect) {
+pub fn draw_button<T: Send + Sync + Copy + 'static>(
+    button: &Button<T>,
+    rect: &Rect,
+    disabled: Option<&Disabled>,
+) {
     let color = match button.state {
+        _ if disabled.is_some() => Color { a: 0.4, ..GRAY },
         ButtonState::Normal => GRAY,
         ButtonState::Hover => LIGHTGRAY,
         ButtonState::Click => DARKGRAY,
@@ -5,6 +5,7 @@ mod text;
 
 pub mod spawner;
 
+pub use button::Disabled;
 pub use container::{FullscreenRoot, UIConstraint, UIContainer, UIRoot, UISize};
 use legion::systems::CommandBuffer;
 use legion::world::SubWorld;
@@ -115,6 +116,20 @@ fn delete_on_click(
     }
 }
 
+/// Remove every UI tree while leaving the rest of the world alone,
+/// so the next state can spawn its own UI.
+#[system]
+pub fn despawn_ui(
+    world: &mut SubWorld,
+    query: &mut Query<(Entity, &UIContainer, &UIRoot)>,
+    commands: &mut CommandBuffer,
+) {
+    query.iter(world).for_each(|(e, container, _)| {
+        delete_container_children_recursive(world, container, commands);
+        commands.remove(*e);
+    });
+}
+
 fn delete_container_children_recursive(
     world: &SubWorld,
     container: &UIContainer,
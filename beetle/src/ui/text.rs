@@ -19,10 +19,6 @@ impl Text {
     }
 }
 
-pub trait TextReceiver {
-    fn send(text: &str);
-}
-
 pub struct SubmitOnEnter(pub Sender<String>);
 pub struct TextInput {
     state: TextInputState,
@@ -49,7 +45,7 @@ pub fn calculate_dynamic_font_size(_: &DynamicText, text: &mut Text, rect: &Rect
         if min >= max {
             return min;
         }
-        let middle = (min + max + 1) / 2;
+        let middle = (min + max).div_ceil(2);
 
         let text_size = measure_text(text, None, middle, 1.0);
         let contained_in_rect = text_size.width <= rect.size.x && text_size.height <= rect.size.y;
@@ -131,11 +127,8 @@ pub fn handle_text_input_submit_on_enter(
             let result = submitter.0.send(text.0.clone());
             text.0.clear();
 
-            if result.is_err() {
-                log::error!(
-                    "There was an error submitting text from the selected input. {}",
-                    result.unwrap_err()
-                );
+            if let Err(err) = result {
+                log::error!("There was an error submitting text from the selected input. {err}");
             }
         }
     }
@@ -4,9 +4,9 @@ use std::{
 };
 
 use common::messages::{ClientMessage, DisconnectReason, ServerMessage};
-use laminar::{Config, ErrorKind, Packet, Socket, SocketEvent};
+use laminar::{Config, ErrorKind, Socket, SocketEvent};
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ConnectionStatus {
     NotConnected,
     Connecting,
@@ -14,6 +14,13 @@ pub enum ConnectionStatus {
     Failed(DisconnectReason),
 }
 
+/// The server to connect to when none is given explicitly.
+pub fn default_server_addr() -> SocketAddr {
+    // FIXME This is not a real server address.
+    let addr_string = std::env::var("SHACKLE_SERVER").unwrap_or("5.78.56.23".to_string());
+    format!("{addr_string}:27008").parse().unwrap()
+}
+
 fn client_socket_config() -> Config {
     Config {
         heartbeat_interval: Some(Duration::from_secs(30)),
@@ -30,19 +37,15 @@ pub trait ConnectionInterface
 where
     Self: Sized,
 {
-    fn new() -> Result<Self, ErrorKind>;
+    fn new(server_addr: SocketAddr) -> Result<Self, ErrorKind>;
     fn send_message(&mut self, message: ClientMessage) -> Result<(), ErrorKind>;
     fn receive_messages(&mut self) -> Vec<ServerMessage>;
 }
 
 impl ConnectionInterface for Connection {
-    fn new() -> Result<Self, ErrorKind> {
+    fn new(server_addr: SocketAddr) -> Result<Self, ErrorKind> {
         // TODO Select a valid port to bind to in a more sophisticated way.
         let socket = Socket::bind_with_config("0.0.0.0:0", client_socket_config())?;
-
-        // FIXME This is not a real server address.
-        let addr_string = std::env::var("SHACKLE_SERVER").unwrap_or("5.78.56.23".to_string());
-        let server_addr = format!("{addr_string}:27008").parse().unwrap();
         println!("{server_addr:?}");
 
         Ok(Self {
@@ -52,13 +55,7 @@ impl ConnectionInterface for Connection {
     }
 
     fn send_message(&mut self, message: ClientMessage) -> Result<(), ErrorKind> {
-        let payload = message.to_payload();
-        let msg_type = MessageType::from(message);
-        let packet = match msg_type {
-            MessageType::ReliableUnordered => Packet::reliable_unordered(self.server_addr, payload),
-            MessageType::Unreliable => Packet::unreliable(self.server_addr, payload),
-        };
-        self.socket.send(packet)?;
+        self.socket.send(message.to_packet(self.server_addr))?;
         self.socket.manual_poll(Instant::now());
         Ok(())
     }
@@ -85,17 +82,3 @@ impl ConnectionInterface for Connection {
         result
     }
 }
-
-enum MessageType {
-    Unreliable,
-    ReliableUnordered,
-}
-
-impl From<ClientMessage> for MessageType {
-    fn from(item: ClientMessage) -> Self {
-        match item {
-            ClientMessage::MoveTo(_) => Self::Unreliable,
-            _ => Self::ReliableUnordered,
-        }
-    }
-}
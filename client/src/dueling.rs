@@ -1,4 +1,4 @@
-use common::{messages::ClientMessage, NetworkID};
+use common::{battle::BattleAction, messages::ClientMessage, NetworkID};
 
 use crate::{connection::ConnectionInterface, Client, ClientError};
 
@@ -10,6 +10,19 @@ pub trait DuelingClient {
         target_id: NetworkID,
         response: bool,
     ) -> Result<(), ClientError>;
+
+    /// Withdraw a challenge this client sent to the given player.
+    fn cancel_challenge(&mut self, target_id: NetworkID) -> Result<(), ClientError>;
+
+    fn send_battle_action(&mut self, action: BattleAction) -> Result<(), ClientError>;
+
+    /// Watch the duel the given player is fighting.
+    fn spectate_duel(&mut self, player_id: NetworkID) -> Result<(), ClientError>;
+
+    fn stop_spectating(&mut self) -> Result<(), ClientError>;
+
+    /// Ask for the best rated duellists and our own place among them.
+    fn request_leaderboard(&mut self) -> Result<(), ClientError>;
 }
 
 impl<T: ConnectionInterface> DuelingClient for Client<T> {
@@ -28,6 +41,36 @@ impl<T: ConnectionInterface> DuelingClient for Client<T> {
         conn.send_message(ClientMessage::RespondToChallenge(target_id, response))?;
         Ok(())
     }
+
+    fn cancel_challenge(&mut self, target_id: NetworkID) -> Result<(), ClientError> {
+        let conn = self.get_connection_mut()?;
+        conn.send_message(ClientMessage::CancelChallenge(target_id))?;
+        Ok(())
+    }
+
+    fn send_battle_action(&mut self, action: BattleAction) -> Result<(), ClientError> {
+        let conn = self.get_connection_mut()?;
+        conn.send_message(ClientMessage::BattleAction(action))?;
+        Ok(())
+    }
+
+    fn spectate_duel(&mut self, player_id: NetworkID) -> Result<(), ClientError> {
+        let conn = self.get_connection_mut()?;
+        conn.send_message(ClientMessage::SpectateDuel(player_id))?;
+        Ok(())
+    }
+
+    fn stop_spectating(&mut self) -> Result<(), ClientError> {
+        let conn = self.get_connection_mut()?;
+        conn.send_message(ClientMessage::StopSpectating)?;
+        Ok(())
+    }
+
+    fn request_leaderboard(&mut self) -> Result<(), ClientError> {
+        let conn = self.get_connection_mut()?;
+        conn.send_message(ClientMessage::RequestLeaderboard)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -41,9 +84,7 @@ mod tests {
     fn test_send_challenge() {
         let mut client = TestClient::already_connected();
         let target_id = NetworkID::new(1);
-        client
-            .send_challenge(target_id.clone())
-            .expect("This should work.");
+        client.send_challenge(target_id).expect("This should work.");
         let binding = client.get_sent_messages();
         let last_message = binding.last().unwrap();
         assert_eq!(*last_message, ClientMessage::IssueChallenge(target_id))
@@ -1,22 +1,38 @@
 mod connection;
 mod dueling;
 pub mod functionality;
-pub use connection::ConnectionStatus;
-use connection::{Connection, ConnectionInterface};
+use connection::default_server_addr;
+pub use connection::{Connection, ConnectionInterface, ConnectionStatus};
 
 use common::{
+    battle::{BattleOutcome, BattleSnapshot, ChallengeResult, Leaderboard, TurnResult},
+    commands::ServerCommand,
     math::Vec2,
-    messages::{ClientMessage, InfoRequestType, InfoSendType, ServerMessage},
-    GameArchetype, NetworkID,
+    messages::{
+        ChatChannel, ChatHistoryEntry, ClientMessage, InfoRequestType, InfoSendType, ServerMessage,
+        PROTOCOL_VERSION,
+    },
+    movement::MoveInput,
+    validation::{sanitize_chat_message, ChatValidationError},
+    ClientMode, GameArchetype, NetworkID,
 };
+use std::net::SocketAddr;
+
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use laminar::ErrorKind;
 
 pub type NetworkClient = Client<Connection>;
 
+/// Identifies the client build to the server for diagnostics.
+pub const BUILD_ID: &str = concat!(env!("CARGO_PKG_NAME"), "-", env!("CARGO_PKG_VERSION"));
+
 pub struct Client<T: ConnectionInterface> {
     connection: Option<(T, ConnectionStatus)>,
     username: Option<String>,
+    player_id: Option<NetworkID>,
+    server_tick_rate: Option<u32>,
+    last_snapshot_tick: Option<u64>,
+    next_move_sequence: u32,
     sender: Sender<ClientEvent>,
     receiver: Receiver<ClientEvent>,
 }
@@ -27,6 +43,10 @@ impl<T: ConnectionInterface> Default for Client<T> {
         Self {
             connection: None,
             username: None,
+            player_id: None,
+            server_tick_rate: None,
+            last_snapshot_tick: None,
+            next_move_sequence: 0,
             sender,
             receiver,
         }
@@ -35,11 +55,19 @@ impl<T: ConnectionInterface> Default for Client<T> {
 
 impl<T: ConnectionInterface> Client<T> {
     pub fn connect(&mut self, username: &str) -> Result<(), ClientError> {
+        self.connect_to(default_server_addr(), username)
+    }
+
+    pub fn connect_to(
+        &mut self,
+        server_addr: SocketAddr,
+        username: &str,
+    ) -> Result<(), ClientError> {
         if self.connection.is_some() {
             return Err(ClientError::DuplicateConnectionError);
         }
 
-        let conn = T::new()?;
+        let conn = T::new(server_addr)?;
         self.connection = Some((conn, ConnectionStatus::Connecting));
 
         let result = self
@@ -47,23 +75,29 @@ impl<T: ConnectionInterface> Client<T> {
             .as_mut()
             .unwrap()
             .0
-            .send_message(ClientMessage::Connect(username.to_string()));
+            .send_message(ClientMessage::Connect {
+                username: username.to_string(),
+                protocol_version: PROTOCOL_VERSION,
+                build: BUILD_ID.to_string(),
+            });
 
         if let Err(err) = result {
             return Err(ClientError::NetworkError(err));
         }
 
         self.username = Some(username.to_string());
+        self.player_id = None;
+        self.server_tick_rate = None;
+        self.last_snapshot_tick = None;
+        self.next_move_sequence = 0;
 
         Ok(())
     }
 
     pub fn connection_status(&self) -> ConnectionStatus {
-        if self.connection.is_none() {
-            ConnectionStatus::NotConnected
-        } else {
-            let conn = self.connection.as_ref().unwrap();
-            conn.1.clone()
+        match &self.connection {
+            Some(conn) => conn.1.clone(),
+            None => ConnectionStatus::NotConnected,
         }
     }
 
@@ -76,16 +110,42 @@ impl<T: ConnectionInterface> Client<T> {
         let messages = conn.0.receive_messages();
 
         messages.iter().for_each(|msg| match msg {
-            ServerMessage::ConnectionAccepted => conn.1 = ConnectionStatus::Connected,
+            ServerMessage::ConnectionAccepted { tick_rate } => {
+                self.server_tick_rate = Some(*tick_rate);
+                conn.1 = ConnectionStatus::Connected;
+            }
             ServerMessage::DisconnectClient(reason) => {
                 self.username = None;
-                conn.1 = ConnectionStatus::Failed(*reason);
+                conn.1 = ConnectionStatus::Failed(reason.clone());
             }
             ServerMessage::SpawnNetworkedEntity(id, entity_type, is_owned) => {
+                if *is_owned {
+                    self.player_id = Some(*id);
+                }
+
                 self.sender
                     .send(ClientEvent::SpawnEntity(*id, *entity_type, *is_owned))
                     .expect("This should send.");
             }
+            ServerMessage::JoinSnapshot(entities) => {
+                entities.iter().for_each(|entity| {
+                    let events = [
+                        ClientEvent::SpawnEntity(entity.id, entity.archetype, false),
+                        ClientEvent::UpdateEntityInfo(
+                            entity.id,
+                            InfoSendType::Identity(entity.name.clone()),
+                        ),
+                        ClientEvent::UpdateEntityInfo(
+                            entity.id,
+                            InfoSendType::Position(entity.position),
+                        ),
+                    ];
+
+                    events.into_iter().for_each(|event| {
+                        self.sender.send(event).expect("This should send.");
+                    });
+                });
+            }
             ServerMessage::DespawnNetworkedEntity(id) => {
                 self.sender
                     .send(ClientEvent::DespawnEntity(*id))
@@ -96,27 +156,119 @@ impl<T: ConnectionInterface> Client<T> {
                     .send(ClientEvent::UpdateEntityInfo(*id, info.clone()))
                     .expect("This should send.");
             }
-            ServerMessage::SendMessage(author, text) => {
+            ServerMessage::PositionSnapshot(tick, positions) => {
+                // Snapshots are sent unreliably, so stale ones may arrive late.
+                if matches!(self.last_snapshot_tick, Some(last) if *tick <= last) {
+                    return;
+                }
+                self.last_snapshot_tick = Some(*tick);
+
+                // The locally controlled player is moved by its own input instead.
+                let positions = positions
+                    .iter()
+                    .filter(|(id, _)| Some(*id) != self.player_id)
+                    .copied()
+                    .collect();
+                self.sender
+                    .send(ClientEvent::PositionSnapshot(*tick, positions))
+                    .expect("This should send.");
+            }
+            ServerMessage::AcknowledgeMove(sequence, pos) => {
+                self.sender
+                    .send(ClientEvent::MoveAcknowledged(*sequence, *pos))
+                    .expect("This should send.");
+            }
+            ServerMessage::SendMessage(channel, author, text) => {
                 self.sender
                     .send(ClientEvent::MessageReceived(
+                        *channel,
                         author.to_string(),
                         text.to_string(),
                     ))
                     .expect("This should send.");
             }
+            ServerMessage::Whisper {
+                author,
+                recipient,
+                text,
+            } => {
+                self.sender
+                    .send(ClientEvent::Whisper {
+                        author: author.clone(),
+                        recipient: recipient.clone(),
+                        text: text.clone(),
+                    })
+                    .expect("This should send.");
+            }
+            ServerMessage::Emote(author, text) => {
+                self.sender
+                    .send(ClientEvent::Emote(author.clone(), text.clone()))
+                    .expect("This should send.");
+            }
+            ServerMessage::ChatHistory(entries) => {
+                self.sender
+                    .send(ClientEvent::ChatHistory(entries.clone()))
+                    .expect("This should send.");
+            }
             ServerMessage::PassAlongChallenge(sender) => {
                 self.sender
                     .send(ClientEvent::ChallengeReceived(*sender))
                     .expect("This should send.");
             }
-            ServerMessage::ChangeClientMode(_new_mode) => {
-                unimplemented!()
+            ServerMessage::ChallengeResolved {
+                challenger,
+                target,
+                result,
+            } => {
+                self.sender
+                    .send(ClientEvent::ChallengeResolved {
+                        challenger: *challenger,
+                        target: *target,
+                        result: *result,
+                    })
+                    .expect("This should send.");
+            }
+            ServerMessage::ChangeClientMode(mode) => {
+                self.sender
+                    .send(ClientEvent::ModeChanged(*mode))
+                    .expect("This should send.");
+            }
+            ServerMessage::BattleStarted(snapshot) => {
+                self.sender
+                    .send(ClientEvent::BattleStarted(snapshot.clone()))
+                    .expect("This should send.");
+            }
+            ServerMessage::BattleTurn(result, snapshot) => {
+                self.sender
+                    .send(ClientEvent::BattleTurn(result.clone(), snapshot.clone()))
+                    .expect("This should send.");
+            }
+            ServerMessage::BattleEnded(outcome) => {
+                self.sender
+                    .send(ClientEvent::BattleEnded(*outcome))
+                    .expect("This should send.");
+            }
+            ServerMessage::SpectatorsChanged(count) => {
+                self.sender
+                    .send(ClientEvent::SpectatorsChanged(*count))
+                    .expect("This should send.");
+            }
+            ServerMessage::Leaderboard(leaderboard) => {
+                self.sender
+                    .send(ClientEvent::Leaderboard(leaderboard.clone()))
+                    .expect("This should send.");
             }
         });
 
         Ok(())
     }
 
+    /// How long each server tick lasts, once the server has said.
+    pub fn server_tick_duration(&self) -> Option<f64> {
+        self.server_tick_rate
+            .map(|tick_rate| 1.0 / tick_rate.max(1) as f64)
+    }
+
     pub fn get_username(&self) -> Option<&str> {
         match &self.username {
             Some(s) => Some(s.as_ref()),
@@ -124,6 +276,11 @@ impl<T: ConnectionInterface> Client<T> {
         }
     }
 
+    /// The id of this client's own player, once the server has spawned it.
+    pub fn get_player_id(&self) -> Option<NetworkID> {
+        self.player_id
+    }
+
     pub fn get_event_receiver(&self) -> Receiver<ClientEvent> {
         self.receiver.clone()
     }
@@ -136,11 +293,19 @@ impl<T: ConnectionInterface> Client<T> {
         Ok(&mut self.connection.as_mut().unwrap().0)
     }
 
-    pub fn move_player(&mut self, pos: Vec2) -> Result<(), ClientError> {
+    /// Send one tick of movement in the given direction. The returned
+    /// input can be replayed once the server acknowledges an earlier one.
+    pub fn move_player(&mut self, direction: Vec2) -> Result<MoveInput, ClientError> {
+        let input = MoveInput {
+            sequence: self.next_move_sequence,
+            direction,
+        };
+
         let conn = self.get_connection_mut()?;
+        conn.send_message(ClientMessage::Move(input))?;
 
-        conn.send_message(ClientMessage::MoveTo(pos))?;
-        Ok(())
+        self.next_move_sequence += 1;
+        Ok(input)
     }
 
     pub fn request_id_archetype(&mut self, id: NetworkID) -> Result<(), ClientError> {
@@ -159,9 +324,34 @@ impl<T: ConnectionInterface> Client<T> {
         Ok(())
     }
 
-    pub fn send_chat_message(&mut self, text: &str) -> Result<(), ClientError> {
+    pub fn send_chat_message(
+        &mut self,
+        channel: ChatChannel,
+        text: &str,
+    ) -> Result<(), ClientError> {
+        let text = sanitize_chat_message(text)?;
+        let conn = self.get_connection_mut()?;
+        conn.send_message(ClientMessage::SendMessage(channel, text))?;
+        Ok(())
+    }
+
+    /// Send a message only the named player will see.
+    pub fn send_whisper(&mut self, target: &str, text: &str) -> Result<(), ClientError> {
+        let text = sanitize_chat_message(text)?;
+        let conn = self.get_connection_mut()?;
+        conn.send_message(ClientMessage::Whisper(target.to_string(), text))?;
+        Ok(())
+    }
+
+    /// Ask the server to run a chat command on this client's behalf.
+    pub fn send_command(&mut self, command: ServerCommand) -> Result<(), ClientError> {
+        let command = match command {
+            ServerCommand::Emote(text) => ServerCommand::Emote(sanitize_chat_message(&text)?),
+            command => command,
+        };
+
         let conn = self.get_connection_mut()?;
-        conn.send_message(ClientMessage::SendMessage(text.to_string()))?;
+        conn.send_message(ClientMessage::Command(command))?;
         Ok(())
     }
 }
@@ -171,6 +361,8 @@ pub enum ClientError {
     DuplicateConnectionError,
     NetworkError(ErrorKind),
     NotConnected,
+    /// A chat message was refused before being sent.
+    InvalidMessage(ChatValidationError),
 }
 
 impl From<ErrorKind> for ClientError {
@@ -179,16 +371,53 @@ impl From<ErrorKind> for ClientError {
     }
 }
 
+impl From<ChatValidationError> for ClientError {
+    fn from(source: ChatValidationError) -> Self {
+        Self::InvalidMessage(source)
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub enum ClientEvent {
     SpawnEntity(NetworkID, GameArchetype, bool),
     DespawnEntity(NetworkID),
     UpdateEntityInfo(NetworkID, InfoSendType),
-    MessageReceived(String, String),
+    // Where every other entity was on the given server tick.
+    PositionSnapshot(u64, Vec<(NetworkID, Vec2)>),
+    MoveAcknowledged(u32, Vec2),
+    // The channel, the author's name and the text.
+    MessageReceived(ChatChannel, String, String),
+    // A private message, which may have been sent by this client.
+    Whisper {
+        author: String,
+        recipient: String,
+        text: String,
+    },
+    // Someone described what they are doing with `/me`.
+    Emote(String, String),
+    // What was said before this client joined, oldest first.
+    ChatHistory(Vec<ChatHistoryEntry>),
     ChallengeReceived(NetworkID),
+    // A challenge this client sent or received is no longer pending.
+    ChallengeResolved {
+        challenger: NetworkID,
+        target: NetworkID,
+        result: ChallengeResult,
+    },
+    // The server has moved this client into a different part of the game.
+    ModeChanged(ClientMode),
+    BattleStarted(BattleSnapshot),
+    BattleTurn(TurnResult, BattleSnapshot),
+    BattleEnded(BattleOutcome),
+    // How many players are watching the duel this client is fighting or watching.
+    SpectatorsChanged(u32),
+    Leaderboard(Leaderboard),
 }
 
 #[cfg(feature = "test_client")]
 pub mod test_utils {
+    use common::messages::DisconnectReason;
+
     use super::*;
 
     pub type TestClient = Client<TestConnection>;
@@ -197,6 +426,8 @@ pub mod test_utils {
         pub fn already_connected() -> Self {
             let mut result = Self::default();
             result.connect("TestUser").expect("This always works.");
+            result.fake_server().accept();
+            result.receive_messages().expect("This always works.");
             result
         }
 
@@ -204,26 +435,71 @@ pub mod test_utils {
             let conn = self.get_connection_mut().expect("Client always exists.");
             conn.client_message_channel.1.try_iter().collect()
         }
+
+        /// The other end of this client's connection.
+        pub fn fake_server(&mut self) -> FakeServer {
+            let conn = self.get_connection_mut().expect("Client always exists.");
+            FakeServer {
+                to_client: conn.server_message_channel.0.clone(),
+                from_client: conn.client_message_channel.1.clone(),
+            }
+        }
+
+        /// Every event produced since this was last called.
+        pub fn take_events(&self) -> Vec<ClientEvent> {
+            self.receiver.try_iter().collect()
+        }
     }
 
-    pub struct TestConnection {
-        client_message_channel: (Sender<ClientMessage>, Receiver<ClientMessage>),
-        server_message_channel: (Sender<ServerMessage>, Receiver<ServerMessage>),
+    /// Plays the part of the server for a `TestClient`. Messages are
+    /// delivered the next time the client receives messages, in the
+    /// order they were sent here.
+    #[derive(Clone)]
+    pub struct FakeServer {
+        to_client: Sender<ServerMessage>,
+        from_client: Receiver<ClientMessage>,
     }
 
-    /*
-    impl TestConnection {
-        fn fake_server_message(&self, msg: ServerMessage) {
-            self.server_message_channel
-                .0
-                .send(msg)
-                .expect("This always works.");
+    impl FakeServer {
+        pub fn send(&self, msg: ServerMessage) {
+            self.to_client.send(msg).expect("This always works.");
+        }
+
+        pub fn send_all(&self, messages: impl IntoIterator<Item = ServerMessage>) {
+            messages.into_iter().for_each(|msg| self.send(msg));
+        }
+
+        /// Deliver the messages in the reverse of the order they were
+        /// sent in, like unreliable packets overtaking each other.
+        pub fn send_out_of_order(&self, messages: impl IntoIterator<Item = ServerMessage>) {
+            let mut messages: Vec<ServerMessage> = messages.into_iter().collect();
+            messages.reverse();
+            self.send_all(messages);
         }
+
+        pub fn accept(&self) {
+            self.send(ServerMessage::ConnectionAccepted { tick_rate: 60 });
+        }
+
+        /// Rejecting a connection looks the same to the client as being
+        /// disconnected after joining.
+        pub fn disconnect(&self, reason: DisconnectReason) {
+            self.send(ServerMessage::DisconnectClient(reason));
+        }
+
+        /// Every message the client has sent that has not been taken yet.
+        pub fn received_messages(&self) -> Vec<ClientMessage> {
+            self.from_client.try_iter().collect()
+        }
+    }
+
+    pub struct TestConnection {
+        client_message_channel: (Sender<ClientMessage>, Receiver<ClientMessage>),
+        server_message_channel: (Sender<ServerMessage>, Receiver<ServerMessage>),
     }
-    */
 
     impl ConnectionInterface for TestConnection {
-        fn new() -> Result<Self, ErrorKind> {
+        fn new(_server_addr: SocketAddr) -> Result<Self, ErrorKind> {
             let client_message_channel = unbounded();
             let server_message_channel = unbounded();
             Ok(Self {
@@ -247,4 +523,153 @@ pub mod test_utils {
 }
 
 #[cfg(test)]
-mod test {}
+mod test {
+    use common::messages::{DisconnectReason, EntitySnapshot};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn connecting_client() -> (TestClient, FakeServer) {
+        let mut client = TestClient::default();
+        client.connect("TestUser").unwrap();
+        let server = client.fake_server();
+        (client, server)
+    }
+
+    #[test]
+    fn test_connection_accepted() {
+        let (mut client, server) = connecting_client();
+        assert_eq!(client.connection_status(), ConnectionStatus::Connecting);
+
+        server.accept();
+        client.receive_messages().unwrap();
+
+        assert_eq!(client.connection_status(), ConnectionStatus::Connected);
+        assert!(matches!(
+            server.received_messages().as_slice(),
+            [ClientMessage::Connect { username, .. }] if username == "TestUser"
+        ));
+    }
+
+    #[test]
+    fn test_connection_rejected() {
+        let (mut client, server) = connecting_client();
+
+        server.disconnect(DisconnectReason::UsernameTaken);
+        client.receive_messages().unwrap();
+
+        assert_eq!(
+            client.connection_status(),
+            ConnectionStatus::Failed(DisconnectReason::UsernameTaken)
+        );
+        assert_eq!(client.get_username(), None);
+    }
+
+    #[test]
+    fn test_disconnected_after_joining() {
+        let mut client = TestClient::already_connected();
+
+        client
+            .fake_server()
+            .disconnect(DisconnectReason::ServerShutdown);
+        client.receive_messages().unwrap();
+
+        assert_eq!(
+            client.connection_status(),
+            ConnectionStatus::Failed(DisconnectReason::ServerShutdown)
+        );
+    }
+
+    #[test]
+    fn test_join_snapshot_events() {
+        let mut client = TestClient::already_connected();
+        let id = NetworkID::new(3);
+        let position = Vec2::new(10.0, 20.0);
+
+        client
+            .fake_server()
+            .send(ServerMessage::JoinSnapshot(vec![EntitySnapshot {
+                id,
+                archetype: GameArchetype::Player,
+                name: "Other".to_string(),
+                position,
+            }]));
+        client.receive_messages().unwrap();
+
+        assert_eq!(
+            client.take_events(),
+            [
+                ClientEvent::SpawnEntity(id, GameArchetype::Player, false),
+                ClientEvent::UpdateEntityInfo(id, InfoSendType::Identity("Other".to_string())),
+                ClientEvent::UpdateEntityInfo(id, InfoSendType::Position(position)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_out_of_order_snapshots_are_dropped() {
+        let mut client = TestClient::already_connected();
+        let id = NetworkID::new(3);
+        let older = Vec2::new(1.0, 1.0);
+        let newer = Vec2::new(2.0, 2.0);
+
+        client.fake_server().send_out_of_order([
+            ServerMessage::PositionSnapshot(1, vec![(id, older)]),
+            ServerMessage::PositionSnapshot(2, vec![(id, newer)]),
+        ]);
+        client.receive_messages().unwrap();
+
+        assert_eq!(
+            client.take_events(),
+            [ClientEvent::PositionSnapshot(2, vec![(id, newer)])]
+        );
+        assert_eq!(client.server_tick_duration(), Some(1.0 / 60.0));
+    }
+
+    #[test]
+    fn test_own_position_is_left_to_prediction() {
+        let mut client = TestClient::already_connected();
+        let own_id = NetworkID::new(0);
+        let other_id = NetworkID::new(1);
+        let pos = Vec2::new(5.0, 5.0);
+
+        client.fake_server().send_all([
+            ServerMessage::SpawnNetworkedEntity(own_id, GameArchetype::Player, true),
+            ServerMessage::PositionSnapshot(1, vec![(own_id, pos), (other_id, pos)]),
+            ServerMessage::AcknowledgeMove(4, pos),
+        ]);
+        client.receive_messages().unwrap();
+
+        assert_eq!(
+            client.take_events(),
+            [
+                ClientEvent::SpawnEntity(own_id, GameArchetype::Player, true),
+                ClientEvent::PositionSnapshot(1, vec![(other_id, pos)]),
+                ClientEvent::MoveAcknowledged(4, pos),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chat_is_checked_before_sending() {
+        let mut client = TestClient::already_connected();
+        let server = client.fake_server();
+
+        let result = client.send_chat_message(ChatChannel::Global, " \n ");
+        assert!(matches!(
+            result,
+            Err(ClientError::InvalidMessage(ChatValidationError::Empty))
+        ));
+
+        client
+            .send_chat_message(ChatChannel::Global, " hi\u{7} ")
+            .unwrap();
+        assert_eq!(
+            server.received_messages().last(),
+            Some(&ClientMessage::SendMessage(
+                ChatChannel::Global,
+                "hi".to_string()
+            ))
+        );
+    }
+}
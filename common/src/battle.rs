@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+use crate::NetworkID;
+
+/// Why a duel challenge is no longer pending.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChallengeResult {
+    Accepted,
+    Declined,
+    /// Nobody answered in time.
+    Expired,
+    /// The challenger withdrew it, or one of the players left or
+    /// started another duel.
+    Cancelled,
+}
+
+/// Everything a combatant can do on their turn.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BattleAction {
+    Attack,
+    /// Halve any damage taken until this combatant's next turn.
+    Defend,
+    /// A heavy strike that can only be used once per battle.
+    Ability,
+    /// Give up. This is allowed at any time, not just on your own turn.
+    Forfeit,
+}
+
+impl std::fmt::Display for BattleAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Attack => write!(f, "Attack"),
+            Self::Defend => write!(f, "Defend"),
+            Self::Ability => write!(f, "Heavy Strike"),
+            Self::Forfeit => write!(f, "Forfeit"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CombatantSnapshot {
+    pub id: NetworkID,
+    pub name: String,
+    pub hp: u32,
+    pub max_hp: u32,
+    pub defending: bool,
+    pub ability_used: bool,
+}
+
+/// Everything about a battle that is shown to the people in it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BattleSnapshot {
+    pub combatants: [CombatantSnapshot; 2],
+    /// The combatant whose turn it is.
+    pub turn: NetworkID,
+    pub round: u32,
+}
+
+impl BattleSnapshot {
+    pub fn combatant(&self, id: NetworkID) -> Option<&CombatantSnapshot> {
+        self.combatants.iter().find(|combatant| combatant.id == id)
+    }
+}
+
+/// What happened on a single turn.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TurnResult {
+    pub actor: NetworkID,
+    pub action: BattleAction,
+    pub damage: u32,
+    /// A line for the turn log, eg. "Alice attacks Bob for 12 damage."
+    pub description: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BattleEnd {
+    Defeated,
+    Forfeited,
+    /// The loser left the server partway through.
+    Disconnected,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BattleOutcome {
+    pub winner: NetworkID,
+    pub loser: NetworkID,
+    pub reason: BattleEnd,
+}
+
+/// A player's place on the duel leaderboard.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LeaderboardEntry {
+    pub rank: u32,
+    pub name: String,
+    pub rating: i32,
+    pub wins: u32,
+    pub losses: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Leaderboard {
+    /// The highest rated players, best first.
+    pub top: Vec<LeaderboardEntry>,
+    /// Where the player who asked stands, if they have fought a duel.
+    pub own: Option<LeaderboardEntry>,
+}
@@ -0,0 +1,321 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::messages::ChatChannel;
+
+/// A slash command that can be typed into the chat box.
+#[derive(Debug, PartialEq)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+}
+
+pub const SAY: CommandSpec = CommandSpec {
+    name: "s",
+    usage: "/s message",
+    description: "Say something only nearby players will hear.",
+};
+pub const WHISPER: CommandSpec = CommandSpec {
+    name: "w",
+    usage: "/w name message",
+    description: "Send a message only the named player will see. Put names with spaces in quotes.",
+};
+pub const WHO: CommandSpec = CommandSpec {
+    name: "who",
+    usage: "/who",
+    description: "List everyone who is online.",
+};
+pub const EMOTE: CommandSpec = CommandSpec {
+    name: "me",
+    usage: "/me action",
+    description: "Describe what your character is doing.",
+};
+pub const ROLL: CommandSpec = CommandSpec {
+    name: "roll",
+    usage: "/roll [dice, eg. 2d6]",
+    description: "Roll some dice for everyone to see. Rolls 1d20 by default.",
+};
+pub const DUEL: CommandSpec = CommandSpec {
+    name: "duel",
+    usage: "/duel name",
+    description: "Challenge a nearby player to a duel.",
+};
+pub const CANCEL: CommandSpec = CommandSpec {
+    name: "cancel",
+    usage: "/cancel name",
+    description: "Withdraw a duel challenge you sent.",
+};
+pub const IGNORE: CommandSpec = CommandSpec {
+    name: "ignore",
+    usage: "/ignore name",
+    description: "Hide or stop hiding messages from a player.",
+};
+pub const HELP: CommandSpec = CommandSpec {
+    name: "help",
+    usage: "/help [command]",
+    description: "List the available commands, or explain one of them.",
+};
+
+/// Every command understood by the chat box, in the order `/help` lists them.
+pub const COMMANDS: &[CommandSpec] = &[SAY, WHISPER, WHO, EMOTE, ROLL, DUEL, CANCEL, IGNORE, HELP];
+
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    let name = name.trim_start_matches('/');
+    COMMANDS
+        .iter()
+        .find(|command| command.name.eq_ignore_ascii_case(name))
+}
+
+/// What the player typed into the chat box.
+#[derive(Debug, PartialEq)]
+pub enum ChatInput {
+    Say(ChatChannel, String),
+    Command(ChatCommand),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ChatCommand {
+    Whisper {
+        target: String,
+        text: String,
+    },
+    Duel(String),
+    CancelDuel(String),
+    Ignore(String),
+    Help(Option<&'static CommandSpec>),
+    /// A command the client cannot answer by itself.
+    Server(ServerCommand),
+}
+
+/// Commands that are run by the server on behalf of a player.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ServerCommand {
+    Who,
+    Emote(String),
+    Roll(DiceRoll),
+}
+
+/// A number of dice with the same number of sides, eg. `2d6`.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct DiceRoll {
+    pub count: u32,
+    pub sides: u32,
+}
+
+impl DiceRoll {
+    pub const MAX_COUNT: u32 = 20;
+    pub const MAX_SIDES: u32 = 1000;
+
+    /// Rolls arrive from clients, so the server checks them again.
+    pub fn is_reasonable(&self) -> bool {
+        (1..=Self::MAX_COUNT).contains(&self.count) && (2..=Self::MAX_SIDES).contains(&self.sides)
+    }
+}
+
+impl Default for DiceRoll {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            sides: 20,
+        }
+    }
+}
+
+impl FromStr for DiceRoll {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_lowercase();
+        let (count, sides) = lowercase.split_once('d').ok_or(())?;
+
+        let roll = Self {
+            count: match count {
+                "" => 1,
+                count => count.parse().map_err(|_| ())?,
+            },
+            sides: sides.parse().map_err(|_| ())?,
+        };
+        if roll.is_reasonable() {
+            Ok(roll)
+        } else {
+            Err(())
+        }
+    }
+}
+
+impl std::fmt::Display for DiceRoll {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CommandError {
+    Unknown(String),
+    Usage(&'static CommandSpec),
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(
+                f,
+                "Unknown command /{name}. Type /help for a list of commands."
+            ),
+            Self::Usage(command) => write!(f, "Usage: {}", command.usage),
+        }
+    }
+}
+
+/// Split the name off the front of the arguments, reading up to the
+/// closing quote if the name is quoted and the first space if not.
+fn split_name(args: &str) -> Option<(&str, &str)> {
+    let (name, rest) = match args.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"')?,
+        None => args.split_once(' ')?,
+    };
+    let rest = rest.trim();
+    (!name.trim().is_empty() && !rest.is_empty()).then_some((name, rest))
+}
+
+/// Split a line from the chat box into either plain chat or a
+/// command. Only lines starting with `/` are treated as commands.
+pub fn parse_chat_input(line: &str) -> Result<ChatInput, CommandError> {
+    let Some(command) = line.strip_prefix('/') else {
+        return Ok(ChatInput::Say(ChatChannel::Global, line.to_string()));
+    };
+
+    let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+    let args = args.trim();
+    let spec = find_command(name).ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+    let usage = || CommandError::Usage(spec);
+
+    let command = match spec.name {
+        "s" if !args.is_empty() => return Ok(ChatInput::Say(ChatChannel::Say, args.to_string())),
+        "w" => {
+            let (target, text) = split_name(args).ok_or_else(usage)?;
+            ChatCommand::Whisper {
+                target: target.to_string(),
+                text: text.to_string(),
+            }
+        }
+        "who" => ChatCommand::Server(ServerCommand::Who),
+        "me" if !args.is_empty() => ChatCommand::Server(ServerCommand::Emote(args.to_string())),
+        "roll" if args.is_empty() => ChatCommand::Server(ServerCommand::Roll(DiceRoll::default())),
+        "roll" => {
+            let roll = args.parse().map_err(|_| usage())?;
+            ChatCommand::Server(ServerCommand::Roll(roll))
+        }
+        "duel" if !args.is_empty() => ChatCommand::Duel(args.to_string()),
+        "cancel" if !args.is_empty() => ChatCommand::CancelDuel(args.to_string()),
+        "ignore" if !args.is_empty() => ChatCommand::Ignore(args.to_string()),
+        "help" if args.is_empty() => ChatCommand::Help(None),
+        "help" => {
+            let topic = find_command(args)
+                .ok_or_else(|| CommandError::Unknown(args.trim_start_matches('/').to_string()))?;
+            ChatCommand::Help(Some(topic))
+        }
+        _ => return Err(usage()),
+    };
+
+    Ok(ChatInput::Command(command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(line: &str) -> ChatCommand {
+        match parse_chat_input(line) {
+            Ok(ChatInput::Command(command)) => command,
+            other => panic!("{line} should be a command, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_chat() {
+        assert_eq!(
+            parse_chat_input("hello /w"),
+            Ok(ChatInput::Say(ChatChannel::Global, "hello /w".to_string()))
+        );
+        assert_eq!(
+            parse_chat_input("/s  psst "),
+            Ok(ChatInput::Say(ChatChannel::Say, "psst".to_string()))
+        );
+        assert_eq!(parse_chat_input("/s"), Err(CommandError::Usage(&SAY)));
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            command("/w Bob  see you there "),
+            ChatCommand::Whisper {
+                target: "Bob".to_string(),
+                text: "see you there".to_string()
+            }
+        );
+        assert_eq!(
+            command("/w \"Sir  Alaric\" well met"),
+            ChatCommand::Whisper {
+                target: "Sir  Alaric".to_string(),
+                text: "well met".to_string()
+            }
+        );
+        assert_eq!(command("/WHO"), ChatCommand::Server(ServerCommand::Who));
+        assert_eq!(
+            command("/me waves"),
+            ChatCommand::Server(ServerCommand::Emote("waves".to_string()))
+        );
+        assert_eq!(
+            command("/roll"),
+            ChatCommand::Server(ServerCommand::Roll(DiceRoll::default()))
+        );
+        assert_eq!(
+            command("/roll d6"),
+            ChatCommand::Server(ServerCommand::Roll(DiceRoll { count: 1, sides: 6 }))
+        );
+        assert_eq!(command("/duel Bob"), ChatCommand::Duel("Bob".to_string()));
+        assert_eq!(
+            command("/cancel Bob"),
+            ChatCommand::CancelDuel("Bob".to_string())
+        );
+        assert_eq!(
+            command("/ignore Bob"),
+            ChatCommand::Ignore("Bob".to_string())
+        );
+        assert_eq!(command("/help"), ChatCommand::Help(None));
+        assert_eq!(command("/help /roll"), ChatCommand::Help(Some(&ROLL)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse_chat_input("/dance"),
+            Err(CommandError::Unknown("dance".to_string()))
+        );
+        assert_eq!(
+            parse_chat_input("/w Bob"),
+            Err(CommandError::Usage(&WHISPER))
+        );
+        assert_eq!(
+            parse_chat_input("/w \"Sir Alaric hello"),
+            Err(CommandError::Usage(&WHISPER))
+        );
+        assert_eq!(
+            parse_chat_input("/w \"Sir Alaric\""),
+            Err(CommandError::Usage(&WHISPER))
+        );
+        assert_eq!(parse_chat_input("/me"), Err(CommandError::Usage(&EMOTE)));
+        assert_eq!(
+            parse_chat_input("/roll 0d6"),
+            Err(CommandError::Usage(&ROLL))
+        );
+        assert_eq!(
+            parse_chat_input("/roll 1d1001"),
+            Err(CommandError::Usage(&ROLL))
+        );
+        assert_eq!(parse_chat_input("/duel"), Err(CommandError::Usage(&DUEL)));
+    }
+}
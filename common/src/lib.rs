@@ -1,8 +1,11 @@
 use math::Vec2;
 use serde::{Deserialize, Serialize};
 
+pub mod battle;
+pub mod commands;
 pub mod math;
 pub mod messages;
+pub mod movement;
 pub mod validation;
 
 pub const PLAY_AREA_SIZE: Vec2 = Vec2 { x: 800.0, y: 600.0 };
@@ -11,7 +14,7 @@ pub const PLAY_AREA_SIZE: Vec2 = Vec2 { x: 800.0, y: 600.0 };
 pub struct NetworkID(usize);
 
 impl NetworkID {
-    pub fn new(id: usize) -> Self {
+    pub const fn new(id: usize) -> Self {
         Self(id)
     }
 }
@@ -22,13 +25,15 @@ impl From<NetworkID> for usize {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
 pub enum GameArchetype {
     Player,
 }
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ClientMode {
     Overworld,
     Battle,
+    /// Watching someone else's duel, without taking part.
+    Spectating,
 }
@@ -5,7 +5,7 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
@@ -26,6 +26,14 @@ impl Vec2 {
     pub fn length(&self) -> f32 {
         self.x.powi(2) + self.y.powi(2)
     }
+
+    pub fn lerp(&self, other: Vec2, t: f32) -> Self {
+        *self + (other - *self) * t
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
 }
 
 impl Mul<f32> for Vec2 {
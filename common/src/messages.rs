@@ -1,18 +1,49 @@
+use std::net::SocketAddr;
+
+use laminar::Packet;
 use serde::{Deserialize, Serialize};
 
-use crate::{math::Vec2, ClientMode, GameArchetype, NetworkID};
+use crate::{
+    battle::{
+        BattleAction, BattleOutcome, BattleSnapshot, ChallengeResult, Leaderboard, TurnResult,
+    },
+    commands::ServerCommand,
+    math::Vec2,
+    movement::MoveInput,
+    ClientMode, GameArchetype, NetworkID,
+};
+
+/// Bumped whenever a change to these messages would stop older
+/// clients and servers from understanding each other. Variants are
+/// encoded by name and fields by position, so that includes adding,
+/// removing or renaming variants and adding, removing or reordering
+/// fields.
+pub const PROTOCOL_VERSION: u32 = 12;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub enum ClientMessage {
-    // The String is the desired Username
-    Connect(String),
+    Connect {
+        username: String,
+        protocol_version: u32,
+        build: String,
+    },
     RequestArchetype(NetworkID),
     RequestEntityInfo(NetworkID, InfoRequestType),
-    SendMessage(String),
-    MoveTo(Vec2),
+    SendMessage(ChatChannel, String),
+    Move(MoveInput),
     IssueChallenge(NetworkID),
     RespondToChallenge(NetworkID, bool),
     Disconnect,
+    // A private message to the player with the given name.
+    Whisper(String, String),
+    Command(ServerCommand),
+    BattleAction(BattleAction),
+    // Withdraw a challenge sent to the given player.
+    CancelChallenge(NetworkID),
+    RequestLeaderboard,
+    // Watch the duel the given player is fighting.
+    SpectateDuel(NetworkID),
+    StopSpectating,
 }
 
 impl ClientMessage {
@@ -23,21 +54,87 @@ impl ClientMessage {
     pub fn from_payload(bytes: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
         rmp_serde::from_slice::<Self>(bytes)
     }
+
+    pub fn delivery_class(&self) -> DeliveryClass {
+        match self {
+            // Inputs are put back in order by the server, and a lost one is
+            // corrected by the next acknowledgement.
+            Self::Move(_) => DeliveryClass::Unreliable,
+            Self::SendMessage(_, _) | Self::Whisper(_, _) | Self::Command(_) => {
+                DeliveryClass::ReliableOrdered(Stream::Chat)
+            }
+            // A challenge cancelled straight after being issued must not
+            // overtake it.
+            Self::IssueChallenge(_)
+            | Self::RespondToChallenge(_, _)
+            | Self::CancelChallenge(_)
+            | Self::BattleAction(_)
+            | Self::SpectateDuel(_)
+            | Self::StopSpectating => DeliveryClass::ReliableOrdered(Stream::Battle),
+            Self::Connect { .. }
+            | Self::RequestArchetype(_)
+            | Self::RequestEntityInfo(_, _)
+            | Self::RequestLeaderboard
+            | Self::Disconnect => DeliveryClass::ReliableUnordered,
+        }
+    }
+
+    pub fn to_packet(&self, addr: SocketAddr) -> Packet {
+        self.delivery_class().packet(addr, self.to_payload())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ServerMessage {
-    ConnectionAccepted,
+    // How many ticks the server runs each second, so snapshot ticks can be turned into times.
+    ConnectionAccepted {
+        tick_rate: u32,
+    },
     SpawnNetworkedEntity(NetworkID, GameArchetype, bool),
+    // Everything a newly connected client needs to know about the world.
+    JoinSnapshot(Vec<EntitySnapshot>),
     DespawnNetworkedEntity(NetworkID),
     SendNetworkedEntityInfo(NetworkID, InfoSendType),
-    SendMessage(String, String),
+    // The u64 is the server tick the snapshot was taken on.
+    PositionSnapshot(u64, Vec<(NetworkID, Vec2)>),
+    // The last movement input processed for the receiving client and where it left them.
+    AcknowledgeMove(u32, Vec2),
+    // The channel, the author's name and the text.
+    SendMessage(ChatChannel, String, String),
     PassAlongChallenge(NetworkID),
     ChangeClientMode(ClientMode),
     DisconnectClient(DisconnectReason),
+    // Sent to both the author and the recipient of a private message.
+    Whisper {
+        author: String,
+        recipient: String,
+        text: String,
+    },
+    // The author's name followed by what they are doing, from `/me`.
+    Emote(String, String),
+    // What was said before the receiving client joined, oldest first.
+    ChatHistory(Vec<ChatHistoryEntry>),
+    // Sent to both players once a challenge has been answered or has lapsed.
+    ChallengeResolved {
+        challenger: NetworkID,
+        target: NetworkID,
+        result: ChallengeResult,
+    },
+    BattleStarted(BattleSnapshot),
+    // What happened on a turn and the state of the battle afterwards.
+    BattleTurn(TurnResult, BattleSnapshot),
+    BattleEnded(BattleOutcome),
+    // How many players are watching the duel the receiving client is part of.
+    SpectatorsChanged(u32),
+    Leaderboard(Leaderboard),
 }
 
 impl ServerMessage {
+    /// A message from the server itself, which players cannot fake.
+    pub fn notice(text: impl Into<String>) -> Self {
+        Self::SendMessage(ChatChannel::System, "SERVER".to_string(), text.into())
+    }
+
     pub fn to_payload(&self) -> Vec<u8> {
         rmp_serde::to_vec(self).unwrap()
     }
@@ -45,6 +142,109 @@ impl ServerMessage {
     pub fn from_payload(bytes: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
         rmp_serde::from_slice::<Self>(bytes)
     }
+
+    pub fn delivery_class(&self) -> DeliveryClass {
+        match self {
+            Self::PositionSnapshot(_, _) => DeliveryClass::UnreliableSequenced(Stream::Positions),
+            Self::AcknowledgeMove(_, _) => DeliveryClass::UnreliableSequenced(Stream::MoveAcks),
+            Self::SendMessage(_, _, _)
+            | Self::Whisper { .. }
+            | Self::Emote(_, _)
+            | Self::ChatHistory(_) => DeliveryClass::ReliableOrdered(Stream::Chat),
+            // Mode changes have to arrive in order with the battle they start
+            // or end, and challenges with whatever became of them.
+            Self::PassAlongChallenge(_)
+            | Self::ChallengeResolved { .. }
+            | Self::ChangeClientMode(_)
+            | Self::BattleStarted(_)
+            | Self::BattleTurn(_, _)
+            | Self::BattleEnded(_)
+            | Self::SpectatorsChanged(_) => DeliveryClass::ReliableOrdered(Stream::Battle),
+            Self::ConnectionAccepted { .. }
+            | Self::SpawnNetworkedEntity(_, _, _)
+            | Self::JoinSnapshot(_)
+            | Self::DespawnNetworkedEntity(_)
+            | Self::SendNetworkedEntityInfo(_, _)
+            | Self::Leaderboard(_)
+            | Self::DisconnectClient(_) => DeliveryClass::ReliableUnordered,
+        }
+    }
+
+    pub fn to_packet(&self, addr: SocketAddr) -> Packet {
+        self.delivery_class().packet(addr, self.to_payload())
+    }
+}
+
+/// How a message travels between the client and the server. Both
+/// ends take this from the message itself so they always agree.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeliveryClass {
+    /// May be lost, duplicated or arrive out of order.
+    Unreliable,
+    /// May be lost, and anything older than the newest message
+    /// already received on the stream is dropped.
+    UnreliableSequenced(Stream),
+    ReliableUnordered,
+    /// Always arrives, in the order it was sent on the stream.
+    ReliableOrdered(Stream),
+}
+
+/// Ordered and sequenced messages are only ordered relative to other
+/// messages on the same stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stream {
+    Chat,
+    Positions,
+    MoveAcks,
+    Battle,
+}
+
+impl DeliveryClass {
+    pub fn packet(self, addr: SocketAddr, payload: Vec<u8>) -> Packet {
+        match self {
+            Self::Unreliable => Packet::unreliable(addr, payload),
+            Self::UnreliableSequenced(stream) => {
+                Packet::unreliable_sequenced(addr, payload, Some(stream as u8))
+            }
+            Self::ReliableUnordered => Packet::reliable_unordered(addr, payload),
+            Self::ReliableOrdered(stream) => {
+                Packet::reliable_ordered(addr, payload, Some(stream as u8))
+            }
+        }
+    }
+}
+
+/// Who a chat message is meant for.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChatChannel {
+    /// Everyone on the server.
+    Global,
+    /// Only players near the speaker.
+    Say,
+    /// Notices from the server. Clients cannot send on this channel.
+    System,
+}
+
+/// A chat message sent before the receiving client joined.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatHistoryEntry {
+    /// How many seconds before the history was sent this was said.
+    /// Clients measure from when it arrives, as their clocks may not
+    /// agree with the server's.
+    pub age_secs: u64,
+    pub channel: ChatChannel,
+    pub author: String,
+    pub text: String,
+}
+
+/// The full state of a single networked entity at the time a
+/// client joined.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EntitySnapshot {
+    pub id: NetworkID,
+    pub archetype: GameArchetype,
+    pub name: String,
+    pub position: Vec2,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -52,13 +252,89 @@ pub enum InfoRequestType {
     Identity,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum InfoSendType {
     Identity(String),
     Position(Vec2),
 }
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum DisconnectReason {
     InvalidUsername,
+    UsernameTaken,
+    ReservedUsername,
+    ServerFull,
+    IncompatibleVersion { server_version: u32 },
+    Kicked { reason: Option<String> },
+    Banned,
+    ServerShutdown,
+}
+
+impl std::fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidUsername => write!(f, "The server rejected your username."),
+            Self::UsernameTaken => write!(f, "Someone with that name is already playing."),
+            Self::ReservedUsername => write!(f, "That name is reserved. Please choose another."),
+            Self::ServerFull => write!(f, "The server is full. Please try again later."),
+            Self::IncompatibleVersion { server_version } => write!(
+                f,
+                "Your game is out of date (version {PROTOCOL_VERSION}, server version {server_version}). Please update to keep playing."
+            ),
+            Self::Kicked { reason: Some(reason) } => {
+                write!(f, "You were kicked from the server: {reason}")
+            }
+            Self::Kicked { reason: None } => write!(f, "You were kicked from the server."),
+            Self::Banned => write!(f, "You are banned from this server."),
+            Self::ServerShutdown => write!(f, "The server has shut down."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An out of date client has to be able to connect and read why it
+    /// was turned away. If this fails, the handshake has changed in a
+    /// way older clients cannot read.
+    #[test]
+    fn test_handshake_encoding_is_stable() {
+        let connect = ClientMessage::Connect {
+            username: "Alice".to_string(),
+            protocol_version: 10,
+            build: "1.0".to_string(),
+        };
+        assert_eq!(
+            connect.to_payload(),
+            b"\x81\xa7Connect\x93\xa5Alice\x0a\xa31.0"
+        );
+
+        let rejection = ServerMessage::DisconnectClient(DisconnectReason::IncompatibleVersion {
+            server_version: 10,
+        });
+        assert_eq!(
+            rejection.to_payload(),
+            b"\x81\xb0DisconnectClient\x81\xb3IncompatibleVersion\x91\x0a"
+        );
+    }
+
+    #[test]
+    fn test_chat_and_positions_have_their_own_streams() {
+        let chat = ServerMessage::notice("Hello!");
+        let snapshot = ServerMessage::PositionSnapshot(1, Vec::new());
+
+        assert_eq!(
+            chat.delivery_class(),
+            DeliveryClass::ReliableOrdered(Stream::Chat)
+        );
+        assert_eq!(
+            ClientMessage::SendMessage(ChatChannel::Say, "Hi".to_string()).delivery_class(),
+            chat.delivery_class()
+        );
+        assert_eq!(
+            snapshot.delivery_class(),
+            DeliveryClass::UnreliableSequenced(Stream::Positions)
+        );
+    }
 }
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{math::Vec2, PLAY_AREA_SIZE};
+
+pub const PLAYER_SPEED: f32 = 4.0;
+/// How many movement inputs a client sends each second while moving,
+/// whatever its frame rate.
+pub const INPUTS_PER_SECOND: u32 = 60;
+/// The most inputs sent at once to catch up after a long frame.
+const MAX_CATCH_UP_INPUTS: u32 = 4;
+
+/// A single tick of movement input from a client. Sequence numbers
+/// let the server acknowledge which inputs it has processed.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct MoveInput {
+    pub sequence: u32,
+    pub direction: Vec2,
+}
+
+/// Move a player one tick in the given direction, keeping them
+/// inside the play area. Both the server and client use this so
+/// predictions line up with the authoritative result. Directions
+/// that are NaN or infinite leave the player where they are.
+pub fn apply_movement(pos: Vec2, direction: Vec2) -> Vec2 {
+    if !direction.is_finite() {
+        return pos;
+    }
+    let direction = Vec2::new(direction.x.clamp(-1.0, 1.0), direction.y.clamp(-1.0, 1.0));
+    clamp_to_play_area(pos + direction * PLAYER_SPEED)
+}
+
+/// Turns frames of any length into a steady stream of movement
+/// inputs, so players move at the same speed on every display.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InputClock {
+    accumulated: f64,
+}
+
+impl InputClock {
+    /// Advance the clock by one frame, returning how many inputs are due.
+    pub fn tick(&mut self, frame_time: f64) -> u32 {
+        let interval = 1.0 / INPUTS_PER_SECOND as f64;
+        // A long hitch should not send a burst of stale inputs.
+        self.accumulated =
+            (self.accumulated + frame_time).min(interval * MAX_CATCH_UP_INPUTS as f64);
+
+        let due = (self.accumulated / interval).floor() as u32;
+        self.accumulated -= due as f64 * interval;
+        due
+    }
+}
+
+pub fn clamp_to_play_area(pos: Vec2) -> Vec2 {
+    Vec2::new(
+        pos.x.clamp(0.0, PLAY_AREA_SIZE.x),
+        pos.y.clamp(0.0, PLAY_AREA_SIZE.y),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_movement() {
+        let result = apply_movement(Vec2::new(100.0, 100.0), Vec2::new(1.0, -1.0));
+        assert_eq!(
+            result,
+            Vec2::new(100.0 + PLAYER_SPEED, 100.0 - PLAYER_SPEED)
+        );
+    }
+
+    #[test]
+    fn test_movement_stays_in_play_area() {
+        let result = apply_movement(Vec2::new(1.0, PLAY_AREA_SIZE.y), Vec2::new(-1.0, 1.0));
+        assert_eq!(result, Vec2::new(0.0, PLAY_AREA_SIZE.y));
+    }
+
+    #[test]
+    fn test_invalid_directions_are_ignored() {
+        let pos = Vec2::new(100.0, 100.0);
+        assert_eq!(apply_movement(pos, Vec2::new(f32::NAN, 1.0)), pos);
+        assert_eq!(apply_movement(pos, Vec2::new(0.0, f32::INFINITY)), pos);
+    }
+
+    #[test]
+    fn test_inputs_do_not_depend_on_frame_rate() {
+        [30, 60, 144, 240].iter().for_each(|fps| {
+            let mut clock = InputClock::default();
+            let inputs: u32 = (0..*fps * 10).map(|_| clock.tick(1.0 / *fps as f64)).sum();
+            assert!(
+                inputs.abs_diff(INPUTS_PER_SECOND * 10) <= 1,
+                "{fps} fps sent {inputs}"
+            );
+        });
+
+        let mut clock = InputClock::default();
+        assert_eq!(clock.tick(2.0), MAX_CATCH_UP_INPUTS);
+    }
+
+    #[test]
+    fn test_oversized_directions_are_clamped() {
+        let result = apply_movement(Vec2::new(100.0, 100.0), Vec2::new(50.0, 0.0));
+        assert_eq!(result, Vec2::new(100.0 + PLAYER_SPEED, 100.0));
+    }
+}
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug, PartialEq)]
 pub enum ValidationError {
     TooShort,
@@ -29,39 +31,190 @@ impl std::fmt::Display for ValidationError {
 const MIN_LENGTH: usize = 3;
 const MAX_LENGTH: usize = 20;
 
+/// The most characters a single chat message may have.
+pub const MAX_CHAT_LENGTH: usize = 200;
+
 const PROFANITY: &str = include_str!("profanity.txt");
 
+/// A list of words that are not allowed in usernames or chat.
+#[derive(Debug, Clone)]
+pub struct WordFilter(Vec<String>);
+
+impl Default for WordFilter {
+    fn default() -> Self {
+        Self::new(PROFANITY)
+    }
+}
+
+impl WordFilter {
+    /// Build a filter from a whitespace separated list of words.
+    pub fn new(list: &str) -> Self {
+        Self(list.split_whitespace().map(|w| w.to_lowercase()).collect())
+    }
+
+    /// Replace every disallowed word in the text with asterisks,
+    /// ignoring case.
+    fn mask(&self, val: &str) -> String {
+        let chars: Vec<char> = val.chars().collect();
+        // Some characters lowercase to more than one, so remember which
+        // character of the original each lowercase one came from.
+        let (lowercase, origins): (Vec<char>, Vec<usize>) = chars
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, c)| c.to_lowercase().map(move |lower| (lower, idx)))
+            .unzip();
+        let mut masked = vec![false; chars.len()];
+
+        self.0.iter().for_each(|word| {
+            let word: Vec<char> = word.chars().collect();
+            if word.is_empty() {
+                return;
+            }
+
+            lowercase
+                .windows(word.len())
+                .enumerate()
+                .filter(|(_, window)| *window == word.as_slice())
+                .for_each(|(start, _)| {
+                    origins[start..start + word.len()]
+                        .iter()
+                        .for_each(|&idx| masked[idx] = true)
+                });
+        });
+
+        chars
+            .iter()
+            .zip(masked)
+            .map(|(c, masked)| if masked { '*' } else { *c })
+            .collect()
+    }
+
+    fn find(&self, val: &str) -> Option<Vec<String>> {
+        let lowercase_val: String = val.chars().flat_map(char::to_lowercase).collect();
+        let found: Vec<String> = self
+            .0
+            .iter()
+            .filter(|word| lowercase_val.contains(word.as_str()))
+            .cloned()
+            .collect();
+
+        if found.is_empty() {
+            None
+        } else {
+            Some(found)
+        }
+    }
+}
+
 pub fn validate_username(name: &str) -> Result<(), ValidationError> {
+    validate_username_with(name, &WordFilter::default())
+}
+
+pub fn validate_username_with(name: &str, filter: &WordFilter) -> Result<(), ValidationError> {
     if name.len() < MIN_LENGTH {
         return Err(ValidationError::TooShort);
     } else if name.len() > MAX_LENGTH {
         return Err(ValidationError::TooLong);
     }
 
-    if let Some(found_profanity) = find_profanity(name) {
+    if let Some(found_profanity) = filter.find(name) {
         return Err(ValidationError::ContainsProfanity(found_profanity));
     }
 
     Ok(())
 }
 
-fn find_profanity(val: &str) -> Option<Vec<String>> {
-    let prof_array: Vec<&str> = PROFANITY.split_whitespace().collect();
+/// What happens to chat messages containing disallowed words.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfanityAction {
+    /// Send the message with the offending words replaced by asterisks.
+    #[default]
+    Mask,
+    /// Refuse to send the message at all.
+    Reject,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ChatValidationError {
+    Empty,
+    TooLong,
+    ContainsProfanity(Vec<String>),
+}
+
+impl std::fmt::Display for ChatValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "There is nothing to send."),
+            Self::TooLong => write!(
+                f,
+                "Messages cannot be longer than {MAX_CHAT_LENGTH} characters."
+            ),
+            Self::ContainsProfanity(profanity) => write!(
+                f,
+                "Your message contains the following disallowed words: [{}]",
+                profanity.join(", ")
+            ),
+        }
+    }
+}
 
-    let lowercase_val = val.to_lowercase();
-    let found_profanity: Vec<String> = prof_array
-        .iter()
-        .filter(|profanity| lowercase_val.contains(*profanity))
-        .map(|p| p.to_string())
+/// Turn tabs and line breaks into spaces, strip other control
+/// characters and surrounding whitespace from a chat message and
+/// check its length. This is everything that can be checked without
+/// knowing the server's word filter.
+pub fn sanitize_chat_message(text: &str) -> Result<String, ChatValidationError> {
+    let text: String = text
+        .chars()
+        .map(|c| {
+            if c.is_control() && c.is_whitespace() {
+                ' '
+            } else {
+                c
+            }
+        })
+        .filter(|c| !c.is_control())
         .collect();
+    let text = text.trim();
 
-    if found_profanity.is_empty() {
-        None
+    if text.is_empty() {
+        Err(ChatValidationError::Empty)
+    } else if text.chars().count() > MAX_CHAT_LENGTH {
+        Err(ChatValidationError::TooLong)
     } else {
-        Some(found_profanity)
+        Ok(text.to_string())
+    }
+}
+
+/// Sanitize a chat message and deal with any disallowed words in it,
+/// returning the text that should actually be sent.
+pub fn validate_chat_message(
+    text: &str,
+    filter: &WordFilter,
+    action: ProfanityAction,
+) -> Result<String, ChatValidationError> {
+    let text = sanitize_chat_message(text)?;
+
+    match (filter.find(&text), action) {
+        (None, _) => Ok(text),
+        (Some(_), ProfanityAction::Mask) => Ok(filter.mask(&text)),
+        (Some(found), ProfanityAction::Reject) => {
+            Err(ChatValidationError::ContainsProfanity(found))
+        }
     }
 }
 
+/// Trim a username and collapse any runs of whitespace inside it
+/// into single spaces.
+pub fn normalize_username(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Whether two usernames would look the same to other players.
+pub fn usernames_match(a: &str, b: &str) -> bool {
+    normalize_username(a).to_lowercase() == normalize_username(b).to_lowercase()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -109,6 +262,79 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_custom_word_filter() {
+        let filter = WordFilter::new("goblin\nTROLL");
+        let expected = Err(ValidationError::ContainsProfanity(
+            vec!["troll".to_string()],
+        ));
+
+        assert_eq!(validate_username_with("Alaric", &filter), Ok(()));
+        assert_eq!(validate_username_with("TrollKing", &filter), expected);
+    }
+
+    #[test]
+    fn test_normalize_username() {
+        assert_eq!(normalize_username("  Captain   Jaeger "), "Captain Jaeger");
+        assert_eq!(normalize_username("Alaric"), "Alaric");
+    }
+
+    #[test]
+    fn test_matching_usernames() {
+        assert!(usernames_match("Alaric", "alaric"));
+        assert!(usernames_match("Captain Jaeger", " captain  JAEGER"));
+        assert!(!usernames_match("Alaric", "Yslith"));
+        assert!(!usernames_match("CaptainJaeger", "Captain Jaeger"));
+    }
+
+    #[test]
+    fn test_sanitize_chat_message() {
+        assert_eq!(
+            sanitize_chat_message("  hello\u{7}\tthere\nfriend\n "),
+            Ok("hello there friend".to_string())
+        );
+        assert_eq!(
+            sanitize_chat_message(" \r\n "),
+            Err(ChatValidationError::Empty)
+        );
+        assert_eq!(
+            sanitize_chat_message(&"a".repeat(MAX_CHAT_LENGTH + 1)),
+            Err(ChatValidationError::TooLong)
+        );
+        assert!(sanitize_chat_message(&"é".repeat(MAX_CHAT_LENGTH)).is_ok());
+    }
+
+    #[test]
+    fn test_chat_profanity() {
+        let filter = WordFilter::new("goblin troll");
+
+        assert_eq!(
+            validate_chat_message(
+                "Beware the TROLL, not the goblins",
+                &filter,
+                ProfanityAction::Mask
+            ),
+            Ok("Beware the *****, not the ******s".to_string())
+        );
+        assert_eq!(
+            validate_chat_message("Hello troll", &filter, ProfanityAction::Reject),
+            Err(ChatValidationError::ContainsProfanity(vec![
+                "troll".to_string()
+            ]))
+        );
+        assert_eq!(
+            validate_chat_message("Hello there", &filter, ProfanityAction::Reject),
+            Ok("Hello there".to_string())
+        );
+
+        // 'İ' lowercases to two characters.
+        let filter = WordFilter::new("İMP");
+        assert_eq!(
+            validate_chat_message("An İMP!", &filter, ProfanityAction::Mask),
+            Ok("An ***!".to_string())
+        );
+    }
+
     #[test]
     fn test_profane_usernames() {
         PROFANITY.split_whitespace().for_each(|profanity| {
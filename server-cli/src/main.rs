@@ -1,7 +1,141 @@
-use log::info;
+use std::{io::BufRead, net::IpAddr, path::PathBuf, thread};
+
+use clap::Parser;
+use crossbeam_channel::Sender;
+use log::{error, info};
+use server::{AdminCommand, ServerConfig, ServerHandle, ADMIN_USAGE};
+
+/// Run a Shackle server. Flags override values from the config file.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// A TOML file to load the server configuration from.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    #[arg(long)]
+    bind_address: Option<IpAddr>,
+
+    #[arg(short, long)]
+    port: Option<u16>,
+
+    /// Server ticks per second.
+    #[arg(long)]
+    tick_rate: Option<u32>,
+
+    /// Seconds without hearing from a client before it is dropped.
+    #[arg(long)]
+    idle_timeout: Option<u64>,
+
+    #[arg(long)]
+    max_players: Option<usize>,
+
+    /// A message of the day shown to every player when they connect.
+    #[arg(long)]
+    motd: Option<String>,
+
+    /// A whitespace separated list of words not allowed in names or chat.
+    #[arg(long)]
+    word_filter: Option<PathBuf>,
+
+    /// Where bans and mutes are kept between runs.
+    #[arg(long)]
+    moderation: Option<PathBuf>,
+}
+
+impl Args {
+    fn apply_overrides(self, config: &mut ServerConfig) {
+        if let Some(bind_address) = self.bind_address {
+            config.bind_address = bind_address;
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(tick_rate) = self.tick_rate {
+            config.tick_rate = tick_rate;
+        }
+        if let Some(idle_timeout) = self.idle_timeout {
+            config.idle_timeout_secs = idle_timeout;
+        }
+        if let Some(max_players) = self.max_players {
+            config.max_players = max_players;
+        }
+        if self.motd.is_some() {
+            config.motd = self.motd;
+        }
+        if self.word_filter.is_some() {
+            config.word_filter_path = self.word_filter;
+        }
+        if self.moderation.is_some() {
+            config.moderation_path = self.moderation;
+        }
+    }
+}
+
+/// Pass commands typed into the console along to the server until
+/// stdin is closed.
+fn read_admin_commands(sender: Sender<AdminCommand>) {
+    std::io::stdin().lock().lines().for_each(|line| {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            return;
+        }
+        if line.trim() == "help" {
+            println!("{ADMIN_USAGE}");
+            return;
+        }
+
+        match line.parse::<AdminCommand>() {
+            Ok(command) => {
+                // The server has stopped if nothing is receiving.
+                let _ = sender.send(command);
+            }
+            Err(err) => println!("{err} Type \"help\" for a list of commands."),
+        }
+    });
+}
 
 fn main() {
     env_logger::init();
     info!("Starting Server CLI");
-    server::server().unwrap();
+
+    let args = Args::parse();
+
+    let mut config = match &args.config {
+        Some(path) => match ServerConfig::from_file(path) {
+            Ok(config) => config,
+            Err(err) => {
+                error!("{err}");
+                std::process::exit(1);
+            }
+        },
+        None => ServerConfig::default(),
+    };
+    args.apply_overrides(&mut config);
+
+    let server = match ServerHandle::start(config) {
+        Ok(server) => server,
+        Err(err) => {
+            error!("Could not start the server: {err:?}");
+            std::process::exit(1);
+        }
+    };
+    println!("Listening at {}", server.local_addr());
+
+    let command_sender = server.admin_command_sender();
+    thread::spawn(move || read_admin_commands(command_sender));
+
+    let replies = server.admin_reply_receiver();
+    thread::spawn(move || replies.iter().for_each(|reply| println!("{reply}")));
+
+    let shutdown = server.shutdown_handle();
+    ctrlc::set_handler(move || shutdown.shutdown())
+        .expect("Could not listen for shutdown signals.");
+
+    if let Err(err) = server.run() {
+        error!("The server stopped with an error: {err:?}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,477 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    io,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    str::FromStr,
+};
+
+use common::{
+    math::Vec2,
+    messages::{DisconnectReason, InfoSendType, ServerMessage},
+    movement::clamp_to_play_area,
+    validation::usernames_match,
+};
+use crossbeam_channel::{Receiver, Sender};
+use laminar::Packet;
+use legion::{system, systems::CommandBuffer, world::SubWorld, EntityStore};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chat::ChatBroadcast, logged_send, message_handling::handle_disconnect, write_atomically,
+    ClientInfo, ClientList, NetworkedEntities, Position, ServerConfig, ShutdownHandle,
+};
+
+pub const ADMIN_USAGE: &str = "\
+Commands:
+  list                      List everyone who is online
+  kick <name> [reason]      Disconnect a player
+  ban <name|ip>             Disconnect a player and stop them reconnecting
+  say <text>                Send a chat message from the server
+  mute <name>               Stop a player's chat messages being sent
+  unmute <name>             Allow a muted player to chat again
+  teleport <name> <x> <y>   Move a player
+  shutdown                  Stop the server
+Names containing spaces can be wrapped in double quotes.";
+
+/// A command typed into the server console by an operator.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    List,
+    Kick {
+        name: String,
+        reason: Option<String>,
+    },
+    Ban(BanTarget),
+    Say(String),
+    Mute(String),
+    Unmute(String),
+    Teleport {
+        name: String,
+        position: Vec2,
+    },
+    Shutdown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BanTarget {
+    Name(String),
+    Address(IpAddr),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseCommandError {
+    Empty,
+    UnknownCommand(String),
+    MissingArgument(&'static str),
+    InvalidArgument(&'static str),
+}
+
+impl Display for ParseCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "No command was given."),
+            Self::UnknownCommand(command) => write!(f, "Unknown command \"{command}\"."),
+            Self::MissingArgument(argument) => write!(f, "Missing argument <{argument}>."),
+            Self::InvalidArgument(argument) => write!(f, "Invalid argument <{argument}>."),
+        }
+    }
+}
+
+impl std::error::Error for ParseCommandError {}
+
+/// Split a line into words, keeping anything wrapped in double
+/// quotes together.
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    line.chars().for_each(|c| match c {
+        '"' => quoted = !quoted,
+        c if c.is_whitespace() && !quoted => {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+        }
+        c => current.push(c),
+    });
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+impl FromStr for AdminCommand {
+    type Err = ParseCommandError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(line);
+        let (command, args) = tokens.split_first().ok_or(ParseCommandError::Empty)?;
+
+        let arg = |index: usize, name: &'static str| {
+            args.get(index)
+                .cloned()
+                .ok_or(ParseCommandError::MissingArgument(name))
+        };
+        let coordinate = |index: usize, name: &'static str| {
+            // NaN and infinity parse, but are not anywhere in the play area.
+            arg(index, name)?
+                .parse::<f32>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .ok_or(ParseCommandError::InvalidArgument(name))
+        };
+        let rest = |index: usize| match args.get(index..) {
+            Some(words) if !words.is_empty() => Some(words.join(" ")),
+            _ => None,
+        };
+
+        match command.to_lowercase().as_str() {
+            "list" => Ok(Self::List),
+            "kick" => Ok(Self::Kick {
+                name: arg(0, "name")?,
+                reason: rest(1),
+            }),
+            "ban" => {
+                let target = arg(0, "name|ip")?;
+                Ok(Self::Ban(match target.parse::<IpAddr>() {
+                    Ok(ip) => BanTarget::Address(ip),
+                    Err(_) => BanTarget::Name(target),
+                }))
+            }
+            "say" => rest(0)
+                .map(Self::Say)
+                .ok_or(ParseCommandError::MissingArgument("text")),
+            "mute" => Ok(Self::Mute(arg(0, "name")?)),
+            "unmute" => Ok(Self::Unmute(arg(0, "name")?)),
+            "teleport" => Ok(Self::Teleport {
+                name: arg(0, "name")?,
+                position: Vec2::new(coordinate(1, "x")?, coordinate(2, "y")?),
+            }),
+            "shutdown" => Ok(Self::Shutdown),
+            _ => Err(ParseCommandError::UnknownCommand(command.clone())),
+        }
+    }
+}
+
+/// Addresses and usernames that may not connect.
+#[derive(Default, Serialize, Deserialize)]
+pub struct BanList {
+    addresses: HashSet<IpAddr>,
+    names: Vec<String>,
+}
+
+impl BanList {
+    pub fn ban_address(&mut self, ip: IpAddr) {
+        self.addresses.insert(ip);
+    }
+
+    pub fn ban_name(&mut self, username: &str) {
+        if !self
+            .names
+            .iter()
+            .any(|name| usernames_match(name, username))
+        {
+            self.names.push(username.to_string());
+        }
+    }
+
+    pub fn is_banned(&self, ip: IpAddr, username: &str) -> bool {
+        self.addresses.contains(&ip)
+            || self
+                .names
+                .iter()
+                .any(|name| usernames_match(name, username))
+    }
+}
+
+/// Players whose chat messages are not passed along.
+#[derive(Default, Serialize, Deserialize)]
+pub struct MutedPlayers(Vec<String>);
+
+impl MutedPlayers {
+    pub fn contains(&self, username: &str) -> bool {
+        self.0.iter().any(|name| usernames_match(name, username))
+    }
+}
+
+/// Bans and mutes applied by operators. These are kept between
+/// runs of the server.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Moderation {
+    // TOML needs plain values written before tables, so this has to come first.
+    pub muted: MutedPlayers,
+    pub bans: BanList,
+}
+
+impl Moderation {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text =
+            toml::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        write_atomically(path, &text)
+    }
+}
+
+/// Where the results of admin commands are sent, for whoever issued
+/// them to show.
+pub struct AdminReplies(pub Sender<String>);
+
+impl AdminReplies {
+    fn send(&self, reply: impl Into<String>) {
+        // Nobody may be listening, which is fine.
+        let _ = self.0.send(reply.into());
+    }
+}
+
+/// Write bans and mutes out as soon as they change, so they are not
+/// lost if the server does not get to shut down cleanly.
+fn save_moderation(moderation: &Moderation, config: &ServerConfig, replies: &AdminReplies) {
+    if let Some(path) = &config.moderation_path {
+        if let Err(err) = moderation.save(path) {
+            replies.send(format!("Could not save to {}: {err}", path.display()));
+        }
+    }
+}
+
+fn find_client(clients: &ClientList, name: &str) -> Option<(SocketAddr, ClientInfo)> {
+    clients
+        .addr_map
+        .iter()
+        .find(|(_, info)| usernames_match(&info.username, name))
+        .map(|(addr, info)| (*addr, info.clone()))
+}
+
+/// Tell a client why it is being removed, then remove it.
+fn remove_client(
+    addr: SocketAddr,
+    reason: DisconnectReason,
+    clients: &mut ClientList,
+    sender: &mut Sender<Packet>,
+    networked_entities: &mut NetworkedEntities,
+    commands: &mut CommandBuffer,
+) {
+    let msg = ServerMessage::DisconnectClient(reason);
+    logged_send(sender, msg.to_packet(addr));
+    handle_disconnect(addr, clients, sender, networked_entities, commands);
+}
+
+fn teleport(
+    name: &str,
+    position: Vec2,
+    world: &mut SubWorld,
+    clients: &ClientList,
+    sender: &mut Sender<Packet>,
+    networked_entities: &NetworkedEntities,
+    replies: &AdminReplies,
+) {
+    let Some((addr, info)) = find_client(clients, name) else {
+        replies.send(format!("No player named {name} is online."));
+        return;
+    };
+
+    let position = clamp_to_play_area(position);
+    if let Some((e, _)) = networked_entities.0.get(&info.player_id) {
+        if let Ok(mut entry) = world.entry_mut(*e) {
+            if let Ok(pos) = entry.get_component_mut::<Position>() {
+                pos.0 = position;
+            }
+        }
+    }
+
+    // Everyone else sees the move in the next snapshot, but the player's
+    // own position is only corrected when they next move, so tell them now.
+    let msg =
+        ServerMessage::SendNetworkedEntityInfo(info.player_id, InfoSendType::Position(position));
+    logged_send(sender, msg.to_packet(addr));
+    replies.send(format!("Teleported {} to {position:?}.", info.username));
+}
+
+#[system]
+#[write_component(Position)]
+#[allow(clippy::too_many_arguments)]
+pub fn run_admin_commands(
+    world: &mut SubWorld,
+    #[resource] admin_commands: &Receiver<AdminCommand>,
+    #[resource] sender: &mut Sender<Packet>,
+    #[resource] clients: &mut ClientList,
+    #[resource] networked_entities: &mut NetworkedEntities,
+    #[resource] moderation: &mut Moderation,
+    #[resource] shutdown: &ShutdownHandle,
+    #[resource] replies: &AdminReplies,
+    #[resource] config: &ServerConfig,
+    commands: &mut CommandBuffer,
+) {
+    admin_commands.try_iter().for_each(|command| match command {
+        AdminCommand::List => {
+            let mut reply = format!("{} player(s) online:", clients.addr_map.len());
+            clients.addr_map.iter().for_each(|(addr, info)| {
+                reply.push_str(&format!("\n  {} ({addr})", info.username));
+            });
+            replies.send(reply);
+        }
+        AdminCommand::Kick { name, reason } => match find_client(clients, &name) {
+            Some((addr, info)) => {
+                replies.send(format!("Kicking {}.", info.username));
+                let reason = DisconnectReason::Kicked { reason };
+                remove_client(addr, reason, clients, sender, networked_entities, commands);
+            }
+            None => replies.send(format!("No player named {name} is online.")),
+        },
+        AdminCommand::Ban(target) => {
+            let banned_addresses: Vec<SocketAddr> = match target {
+                BanTarget::Name(name) => {
+                    moderation.bans.ban_name(&name);
+                    find_client(clients, &name)
+                        .map(|(addr, _)| {
+                            moderation.bans.ban_address(addr.ip());
+                            vec![addr]
+                        })
+                        .unwrap_or_default()
+                }
+                BanTarget::Address(ip) => {
+                    moderation.bans.ban_address(ip);
+                    clients
+                        .all_addresses()
+                        .into_iter()
+                        .filter(|addr| addr.ip() == ip)
+                        .collect()
+                }
+            };
+
+            save_moderation(moderation, config, replies);
+            replies.send(format!(
+                "Banned. {} client(s) disconnected.",
+                banned_addresses.len()
+            ));
+            banned_addresses.into_iter().for_each(|addr| {
+                let reason = DisconnectReason::Banned;
+                remove_client(addr, reason, clients, sender, networked_entities, commands);
+            });
+        }
+        AdminCommand::Say(text) => {
+            commands.push((ChatBroadcast(ServerMessage::notice(text)),));
+        }
+        AdminCommand::Mute(name) => {
+            if !moderation.muted.contains(&name) {
+                moderation.muted.0.push(name.clone());
+            }
+            save_moderation(moderation, config, replies);
+            replies.send(format!("Muted {name}."));
+        }
+        AdminCommand::Unmute(name) => {
+            moderation
+                .muted
+                .0
+                .retain(|muted| !usernames_match(muted, &name));
+            save_moderation(moderation, config, replies);
+            replies.send(format!("Unmuted {name}."));
+        }
+        AdminCommand::Teleport { name, position } => {
+            teleport(
+                &name,
+                position,
+                world,
+                clients,
+                sender,
+                networked_entities,
+                replies,
+            );
+        }
+        AdminCommand::Shutdown => {
+            shutdown.shutdown();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kick_with_quoted_name_and_reason() {
+        let command: AdminCommand = "kick \"Big Bob\" too much  spam".parse().unwrap();
+
+        assert_eq!(
+            command,
+            AdminCommand::Kick {
+                name: "Big Bob".to_string(),
+                reason: Some("too much spam".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_ban_target() {
+        let by_ip: AdminCommand = "ban 10.0.0.1".parse().unwrap();
+        let by_name: AdminCommand = "ban Bob".parse().unwrap();
+
+        assert_eq!(
+            by_ip,
+            AdminCommand::Ban(BanTarget::Address("10.0.0.1".parse().unwrap()))
+        );
+        assert_eq!(
+            by_name,
+            AdminCommand::Ban(BanTarget::Name("Bob".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!("".parse::<AdminCommand>(), Err(ParseCommandError::Empty));
+        assert_eq!(
+            "teleport Bob 1".parse::<AdminCommand>(),
+            Err(ParseCommandError::MissingArgument("y"))
+        );
+        assert_eq!(
+            "teleport Bob 1 up".parse::<AdminCommand>(),
+            Err(ParseCommandError::InvalidArgument("y"))
+        );
+        assert_eq!(
+            "teleport Bob NaN 0".parse::<AdminCommand>(),
+            Err(ParseCommandError::InvalidArgument("x"))
+        );
+        assert_eq!(
+            "teleport Bob 0 -inf".parse::<AdminCommand>(),
+            Err(ParseCommandError::InvalidArgument("y"))
+        );
+        assert!(matches!(
+            "dance".parse::<AdminCommand>(),
+            Err(ParseCommandError::UnknownCommand(_))
+        ));
+    }
+
+    #[test]
+    fn test_ban_list_matches_names_loosely() {
+        let mut ban_list = BanList::default();
+        ban_list.ban_name("Bob");
+
+        let ip = "10.0.0.1".parse().unwrap();
+        assert!(ban_list.is_banned(ip, "bob"));
+        assert!(!ban_list.is_banned(ip, "Alice"));
+    }
+
+    #[test]
+    fn test_moderation_round_trips_through_toml() {
+        let mut moderation = Moderation::default();
+        moderation.bans.ban_name("Bob");
+        moderation.bans.ban_address("10.0.0.1".parse().unwrap());
+        moderation.muted.0.push("Alice".to_string());
+
+        let text = toml::to_string(&moderation).unwrap();
+        let loaded: Moderation = toml::from_str(&text).unwrap();
+
+        assert!(loaded.bans.is_banned("10.0.0.1".parse().unwrap(), "Carol"));
+        assert!(loaded.bans.is_banned("10.0.0.2".parse().unwrap(), "Bob"));
+        assert!(loaded.muted.contains("Alice"));
+    }
+}
@@ -0,0 +1,667 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use common::{
+    battle::{
+        BattleAction, BattleEnd, BattleOutcome, BattleSnapshot, CombatantSnapshot, TurnResult,
+    },
+    messages::ServerMessage,
+    ClientMode, NetworkID,
+};
+use crossbeam_channel::Sender;
+use laminar::Packet;
+use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, Query};
+use log::{error, info};
+use rand::Rng;
+
+use crate::{chat::ChatBroadcast, logged_send, rating::Ratings, ClientList, ServerConfig};
+
+const MAX_HP: u32 = 100;
+const ATTACK_DAMAGE: std::ops::RangeInclusive<u32> = 10..=18;
+const ABILITY_DAMAGE: std::ops::RangeInclusive<u32> = 25..=35;
+
+struct Fighter {
+    id: NetworkID,
+    name: String,
+    hp: u32,
+    defending: bool,
+    ability_used: bool,
+}
+
+impl Fighter {
+    fn new(id: NetworkID, name: &str) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            hp: MAX_HP,
+            defending: false,
+            ability_used: false,
+        }
+    }
+
+    fn snapshot(&self) -> CombatantSnapshot {
+        CombatantSnapshot {
+            id: self.id,
+            name: self.name.clone(),
+            hp: self.hp,
+            max_hp: MAX_HP,
+            defending: self.defending,
+            ability_used: self.ability_used,
+        }
+    }
+}
+
+/// Why the server refused a battle action.
+#[derive(Debug, PartialEq)]
+pub enum ActionError {
+    NotYourTurn,
+    AbilityUsed,
+    BattleOver,
+}
+
+impl std::fmt::Display for ActionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NotYourTurn => write!(f, "It is not your turn."),
+            Self::AbilityUsed => write!(f, "You have already used your heavy strike."),
+            Self::BattleOver => write!(f, "The duel is already over."),
+        }
+    }
+}
+
+/// A duel between two players. Each battle is its own entity, which
+/// is removed once the battle has been resolved.
+pub struct Battle {
+    fighters: [Fighter; 2],
+    /// The index of the fighter whose turn it is.
+    turn: usize,
+    turn_started: Instant,
+    round: u32,
+    outcome: Option<BattleOutcome>,
+    /// Players watching the battle, who are sent every turn but
+    /// cannot act.
+    spectators: Vec<NetworkID>,
+}
+
+impl Battle {
+    /// The first fighter takes the first turn.
+    pub fn new(first: (NetworkID, &str), second: (NetworkID, &str)) -> Self {
+        Self {
+            fighters: [
+                Fighter::new(first.0, first.1),
+                Fighter::new(second.0, second.1),
+            ],
+            turn: 0,
+            turn_started: Instant::now(),
+            round: 1,
+            outcome: None,
+            spectators: Vec::new(),
+        }
+    }
+
+    pub fn participants(&self) -> [NetworkID; 2] {
+        [self.fighters[0].id, self.fighters[1].id]
+    }
+
+    pub fn includes(&self, id: NetworkID) -> bool {
+        self.participants().contains(&id)
+    }
+
+    /// Both fighters followed by everyone watching.
+    fn audience(&self) -> Vec<NetworkID> {
+        let mut audience = self.participants().to_vec();
+        audience.extend(&self.spectators);
+        audience
+    }
+
+    pub fn outcome(&self) -> Option<BattleOutcome> {
+        self.outcome
+    }
+
+    pub fn snapshot(&self) -> BattleSnapshot {
+        BattleSnapshot {
+            combatants: [self.fighters[0].snapshot(), self.fighters[1].snapshot()],
+            turn: self.fighters[self.turn].id,
+            round: self.round,
+        }
+    }
+
+    fn name_of(&self, id: NetworkID) -> &str {
+        self.fighters
+            .iter()
+            .find(|fighter| fighter.id == id)
+            .map(|fighter| fighter.name.as_str())
+            .unwrap_or_default()
+    }
+
+    /// End the battle in the other fighter's favour.
+    fn lose(&mut self, loser: usize, reason: BattleEnd) {
+        self.outcome = Some(BattleOutcome {
+            winner: self.fighters[1 - loser].id,
+            loser: self.fighters[loser].id,
+            reason,
+        });
+    }
+
+    /// Forfeit the duel on behalf of a fighter who has taken too long
+    /// over their turn.
+    pub fn time_out(&mut self, timeout: Duration, now: Instant) -> Option<TurnResult> {
+        if self.outcome.is_some() || now.duration_since(self.turn_started) < timeout {
+            return None;
+        }
+
+        let idle = &self.fighters[self.turn];
+        let result = TurnResult {
+            actor: idle.id,
+            action: BattleAction::Forfeit,
+            damage: 0,
+            description: format!("{} ran out of time and forfeits the duel.", idle.name),
+        };
+        self.lose(self.turn, BattleEnd::Forfeited);
+        Some(result)
+    }
+
+    /// Hand the battle to whoever is left when a fighter leaves the server.
+    pub fn abandon(&mut self, id: NetworkID) {
+        if let Some(index) = self.fighters.iter().position(|fighter| fighter.id == id) {
+            if self.outcome.is_none() {
+                self.lose(index, BattleEnd::Disconnected);
+            }
+        }
+    }
+
+    /// Check and apply a fighter's action. The actor must be one of
+    /// the fighters in this battle.
+    pub fn take_turn(
+        &mut self,
+        actor: NetworkID,
+        action: BattleAction,
+        rng: &mut impl Rng,
+    ) -> Result<TurnResult, ActionError> {
+        if self.outcome.is_some() {
+            return Err(ActionError::BattleOver);
+        }
+
+        let index = self
+            .fighters
+            .iter()
+            .position(|fighter| fighter.id == actor)
+            .expect("Only fighters in this battle can act in it.");
+        let name = self.fighters[index].name.clone();
+        let target = 1 - index;
+        let target_name = self.fighters[target].name.clone();
+
+        if action == BattleAction::Forfeit {
+            self.lose(index, BattleEnd::Forfeited);
+            return Ok(TurnResult {
+                actor,
+                action,
+                damage: 0,
+                description: format!("{name} forfeits the duel."),
+            });
+        }
+
+        if index != self.turn {
+            return Err(ActionError::NotYourTurn);
+        }
+        if action == BattleAction::Ability && self.fighters[index].ability_used {
+            return Err(ActionError::AbilityUsed);
+        }
+
+        // Defending only lasts until the defender's next turn.
+        self.fighters[index].defending = false;
+
+        let (damage, description) = match action {
+            BattleAction::Attack | BattleAction::Ability => {
+                let (range, verb) = if action == BattleAction::Attack {
+                    (ATTACK_DAMAGE, "attacks")
+                } else {
+                    self.fighters[index].ability_used = true;
+                    (ABILITY_DAMAGE, "lands a heavy strike on")
+                };
+
+                let mut damage = rng.gen_range(range);
+                let guard = if self.fighters[target].defending {
+                    damage /= 2;
+                    " through their guard"
+                } else {
+                    ""
+                };
+
+                let target_fighter = &mut self.fighters[target];
+                target_fighter.hp = target_fighter.hp.saturating_sub(damage);
+                let description =
+                    format!("{name} {verb} {target_name} for {damage} damage{guard}.");
+                (damage, description)
+            }
+            BattleAction::Defend => {
+                self.fighters[index].defending = true;
+                (0, format!("{name} raises their guard."))
+            }
+            BattleAction::Forfeit => unreachable!("Forfeits are handled above."),
+        };
+
+        if self.fighters[target].hp == 0 {
+            self.lose(target, BattleEnd::Defeated);
+        } else {
+            self.turn = target;
+            self.turn_started = Instant::now();
+            if target == 0 {
+                self.round += 1;
+            }
+        }
+
+        Ok(TurnResult {
+            actor,
+            action,
+            damage,
+            description,
+        })
+    }
+}
+
+/// A battle action sent by the client at the given address.
+pub struct BattleActionRequest(pub SocketAddr, pub BattleAction);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SpectateRequest {
+    /// Watch the duel the given player is fighting.
+    Watch(NetworkID),
+    Stop,
+}
+
+/// A spectating message sent by the client at the given address.
+pub struct SpectateMessage(pub SocketAddr, pub SpectateRequest);
+
+fn send_to(
+    ids: &[NetworkID],
+    msg: &ServerMessage,
+    clients: &ClientList,
+    sender: &mut Sender<Packet>,
+) {
+    ids.iter()
+        .filter_map(|id| clients.get_by_netid(*id))
+        .for_each(|(addr, _)| logged_send(sender, msg.to_packet(*addr)));
+}
+
+fn set_mode(ids: &[NetworkID], mode: ClientMode, clients: &mut ClientList) {
+    clients
+        .addr_map
+        .values_mut()
+        .filter(|info| ids.contains(&info.player_id))
+        .for_each(|info| info.mode = mode);
+}
+
+fn send_spectator_count(battle: &Battle, clients: &ClientList, sender: &mut Sender<Packet>) {
+    let msg = ServerMessage::SpectatorsChanged(battle.spectators.len() as u32);
+    send_to(&battle.audience(), &msg, clients, sender);
+}
+
+/// Move both players into a new battle and let everyone know.
+pub fn start_battle(
+    battle: Battle,
+    clients: &mut ClientList,
+    sender: &mut Sender<Packet>,
+    commands: &mut CommandBuffer,
+) {
+    let participants = battle.participants();
+    set_mode(&participants, ClientMode::Battle, clients);
+
+    let mode_msg = ServerMessage::ChangeClientMode(ClientMode::Battle);
+    send_to(&participants, &mode_msg, clients, sender);
+    let start_msg = ServerMessage::BattleStarted(battle.snapshot());
+    send_to(&participants, &start_msg, clients, sender);
+
+    let [first, second] = &battle.fighters;
+    info!("{} and {} have begun a duel.", first.name, second.name);
+    let text = format!("{} and {} have begun a duel!", first.name, second.name);
+    commands.push((ChatBroadcast(ServerMessage::notice(text)),));
+    commands.push((battle,));
+}
+
+fn announcement(battle: &Battle, outcome: BattleOutcome) -> String {
+    let winner = battle.name_of(outcome.winner);
+    let loser = battle.name_of(outcome.loser);
+
+    match outcome.reason {
+        BattleEnd::Defeated => format!("{winner} defeated {loser} in a duel!"),
+        BattleEnd::Forfeited => format!("{loser} forfeited their duel against {winner}."),
+        BattleEnd::Disconnected => {
+            format!("{winner} won their duel against {loser}, who left the server.")
+        }
+    }
+}
+
+/// Move points from the loser to the winner, and tell each of them
+/// where they now stand.
+fn update_ratings(
+    battle: &Battle,
+    outcome: BattleOutcome,
+    ratings: &mut Ratings,
+    clients: &ClientList,
+    sender: &mut Sender<Packet>,
+) {
+    let change = ratings.record_duel(
+        battle.name_of(outcome.winner),
+        battle.name_of(outcome.loser),
+    );
+
+    [(outcome.winner, change), (outcome.loser, -change)]
+        .iter()
+        .filter_map(|(id, change)| Some((clients.get_by_netid(*id)?, change)))
+        .for_each(|((addr, info), change)| {
+            let Some(rating) = ratings.get(&info.username) else {
+                return;
+            };
+            let text = format!("Your rating is now {} ({change:+}).", rating.rating);
+            logged_send(sender, ServerMessage::notice(text).to_packet(*addr));
+        });
+}
+
+/// Apply every battle action sent this tick and forfeit for anyone
+/// who has run out of time, then send both players of any finished
+/// battle back to the overworld.
+#[system]
+#[allow(clippy::too_many_arguments)]
+pub fn resolve_battles(
+    request_query: &mut Query<(Entity, &BattleActionRequest)>,
+    battle_query: &mut Query<(Entity, &mut Battle)>,
+    world: &mut SubWorld,
+    #[resource] sender: &mut Sender<Packet>,
+    #[resource] clients: &mut ClientList,
+    #[resource] ratings: &mut Ratings,
+    #[resource] config: &ServerConfig,
+    commands: &mut CommandBuffer,
+) {
+    let requests: Vec<(SocketAddr, BattleAction)> = request_query
+        .iter(world)
+        .map(|(message_entity, request)| {
+            commands.remove(*message_entity);
+            (request.0, request.1)
+        })
+        .collect();
+
+    let mut rng = rand::thread_rng();
+    battle_query.iter_mut(world).for_each(|(entity, battle)| {
+        requests.iter().for_each(|(addr, action)| {
+            let Some(info) = clients.addr_map.get(addr) else {
+                return;
+            };
+            if !battle.includes(info.player_id) {
+                return;
+            }
+
+            match battle.take_turn(info.player_id, *action, &mut rng) {
+                Ok(result) => {
+                    let msg = ServerMessage::BattleTurn(result, battle.snapshot());
+                    send_to(&battle.audience(), &msg, clients, sender);
+                }
+                Err(err) => {
+                    let msg = ServerMessage::notice(err.to_string());
+                    logged_send(sender, msg.to_packet(*addr));
+                }
+            }
+        });
+
+        if let Some(result) = battle.time_out(config.turn_timeout(), Instant::now()) {
+            let msg = ServerMessage::BattleTurn(result, battle.snapshot());
+            send_to(&battle.audience(), &msg, clients, sender);
+        }
+
+        // Anyone who left partway through loses.
+        let missing = battle
+            .participants()
+            .into_iter()
+            .find(|id| clients.get_by_netid(*id).is_none());
+        if let Some(id) = missing {
+            battle.abandon(id);
+        }
+
+        let Some(outcome) = battle.outcome() else {
+            return;
+        };
+
+        // Spectators see the end of the duel and go back with the fighters.
+        let audience = battle.audience();
+        send_to(
+            &audience,
+            &ServerMessage::BattleEnded(outcome),
+            clients,
+            sender,
+        );
+        update_ratings(battle, outcome, ratings, clients, sender);
+        if let Some(path) = &config.ratings_path {
+            if let Err(err) = ratings.save(path) {
+                error!("Could not save ratings to {}: {err}", path.display());
+            }
+        }
+        let mode_msg = ServerMessage::ChangeClientMode(ClientMode::Overworld);
+        send_to(&audience, &mode_msg, clients, sender);
+        set_mode(&audience, ClientMode::Overworld, clients);
+
+        let text = announcement(battle, outcome);
+        commands.push((ChatBroadcast(ServerMessage::notice(text)),));
+        commands.remove(*entity);
+    });
+}
+
+fn send_notice(text: &str, addr: SocketAddr, sender: &mut Sender<Packet>) {
+    logged_send(sender, ServerMessage::notice(text).to_packet(addr));
+}
+
+/// Let players start and stop watching duels, and forget spectators
+/// who have left the server.
+#[system]
+pub fn handle_spectators(
+    request_query: &mut Query<(Entity, &SpectateMessage)>,
+    battle_query: &mut Query<&mut Battle>,
+    world: &mut SubWorld,
+    #[resource] sender: &mut Sender<Packet>,
+    #[resource] clients: &mut ClientList,
+    commands: &mut CommandBuffer,
+) {
+    let requests: Vec<(SocketAddr, SpectateRequest)> = request_query
+        .iter(world)
+        .map(|(message_entity, request)| {
+            commands.remove(*message_entity);
+            (request.0, request.1)
+        })
+        .collect();
+
+    requests.into_iter().for_each(|(addr, request)| {
+        let Some(info) = clients.addr_map.get(&addr) else {
+            return;
+        };
+        let (viewer, mode) = (info.player_id, info.mode);
+
+        match request {
+            SpectateRequest::Watch(target) => {
+                let refusal = match mode {
+                    ClientMode::Overworld => None,
+                    ClientMode::Battle => Some("You are already in a duel."),
+                    ClientMode::Spectating => Some("You are already watching a duel."),
+                };
+                if let Some(refusal) = refusal {
+                    send_notice(refusal, addr, sender);
+                    return;
+                }
+
+                let Some(battle) = battle_query
+                    .iter_mut(world)
+                    .find(|battle| battle.includes(target) && battle.outcome().is_none())
+                else {
+                    send_notice("That player is not in a duel.", addr, sender);
+                    return;
+                };
+
+                battle.spectators.push(viewer);
+                set_mode(&[viewer], ClientMode::Spectating, clients);
+                let mode_msg = ServerMessage::ChangeClientMode(ClientMode::Spectating);
+                logged_send(sender, mode_msg.to_packet(addr));
+                let start_msg = ServerMessage::BattleStarted(battle.snapshot());
+                logged_send(sender, start_msg.to_packet(addr));
+                send_spectator_count(battle, clients, sender);
+            }
+            SpectateRequest::Stop => {
+                let Some(battle) = battle_query
+                    .iter_mut(world)
+                    .find(|battle| battle.spectators.contains(&viewer))
+                else {
+                    send_notice("You are not watching a duel.", addr, sender);
+                    return;
+                };
+
+                battle.spectators.retain(|id| *id != viewer);
+                set_mode(&[viewer], ClientMode::Overworld, clients);
+                let mode_msg = ServerMessage::ChangeClientMode(ClientMode::Overworld);
+                logged_send(sender, mode_msg.to_packet(addr));
+                send_spectator_count(battle, clients, sender);
+            }
+        }
+    });
+
+    battle_query.iter_mut(world).for_each(|battle| {
+        let watching = battle.spectators.len();
+        battle
+            .spectators
+            .retain(|id| clients.get_by_netid(*id).is_some());
+        if battle.spectators.len() != watching {
+            send_spectator_count(battle, clients, sender);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    const ALICE: NetworkID = NetworkID::new(1);
+    const BOB: NetworkID = NetworkID::new(2);
+
+    fn battle() -> Battle {
+        Battle::new((ALICE, "Alice"), (BOB, "Bob"))
+    }
+
+    #[test]
+    fn test_turns_alternate() {
+        let mut battle = battle();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert_eq!(
+            battle.take_turn(BOB, BattleAction::Attack, &mut rng),
+            Err(ActionError::NotYourTurn)
+        );
+
+        let result = battle
+            .take_turn(ALICE, BattleAction::Attack, &mut rng)
+            .unwrap();
+        assert!(ATTACK_DAMAGE.contains(&result.damage));
+
+        let snapshot = battle.snapshot();
+        assert_eq!(snapshot.turn, BOB);
+        assert_eq!(snapshot.combatant(BOB).unwrap().hp, MAX_HP - result.damage);
+
+        battle
+            .take_turn(BOB, BattleAction::Defend, &mut rng)
+            .unwrap();
+        assert_eq!(battle.snapshot().round, 2);
+    }
+
+    #[test]
+    fn test_defending_halves_damage() {
+        let mut battle = battle();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        battle
+            .take_turn(ALICE, BattleAction::Defend, &mut rng)
+            .unwrap();
+        let result = battle
+            .take_turn(BOB, BattleAction::Attack, &mut rng)
+            .unwrap();
+
+        assert!(result.damage <= ATTACK_DAMAGE.end() / 2);
+        assert!(result.description.ends_with("through their guard."));
+    }
+
+    #[test]
+    fn test_ability_can_only_be_used_once() {
+        let mut battle = battle();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        battle
+            .take_turn(ALICE, BattleAction::Ability, &mut rng)
+            .unwrap();
+        battle
+            .take_turn(BOB, BattleAction::Defend, &mut rng)
+            .unwrap();
+
+        assert_eq!(
+            battle.take_turn(ALICE, BattleAction::Ability, &mut rng),
+            Err(ActionError::AbilityUsed)
+        );
+    }
+
+    #[test]
+    fn test_battle_ends() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut battle = battle();
+        while battle.outcome().is_none() {
+            let actor = battle.snapshot().turn;
+            battle
+                .take_turn(actor, BattleAction::Attack, &mut rng)
+                .unwrap();
+        }
+
+        let outcome = battle.outcome().unwrap();
+        assert_eq!(outcome.reason, BattleEnd::Defeated);
+        assert_eq!(battle.snapshot().combatant(outcome.loser).unwrap().hp, 0);
+        assert_eq!(
+            battle.take_turn(outcome.winner, BattleAction::Attack, &mut rng),
+            Err(ActionError::BattleOver)
+        );
+
+        // Forfeiting does not have to wait for your turn.
+        let mut battle = self::battle();
+        battle
+            .take_turn(BOB, BattleAction::Forfeit, &mut rng)
+            .unwrap();
+        assert_eq!(
+            battle.outcome(),
+            Some(BattleOutcome {
+                winner: ALICE,
+                loser: BOB,
+                reason: BattleEnd::Forfeited
+            })
+        );
+    }
+
+    #[test]
+    fn test_idle_fighters_forfeit() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let timeout = Duration::from_secs(60);
+        let mut battle = battle();
+        let start = Instant::now();
+
+        assert_eq!(battle.time_out(timeout, start), None);
+        battle
+            .take_turn(ALICE, BattleAction::Attack, &mut rng)
+            .unwrap();
+        assert_eq!(battle.time_out(timeout, Instant::now()), None);
+
+        let result = battle.time_out(timeout, Instant::now() + timeout).unwrap();
+        assert_eq!((result.actor, result.action), (BOB, BattleAction::Forfeit));
+        assert_eq!(
+            battle.outcome(),
+            Some(BattleOutcome {
+                winner: ALICE,
+                loser: BOB,
+                reason: BattleEnd::Forfeited
+            })
+        );
+        assert_eq!(battle.time_out(timeout, Instant::now() + timeout), None);
+    }
+}
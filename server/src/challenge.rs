@@ -0,0 +1,296 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use common::{battle::ChallengeResult, messages::ServerMessage, ClientMode, NetworkID};
+use crossbeam_channel::Sender;
+use laminar::Packet;
+use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, Query};
+use log::error;
+
+use crate::{
+    battle::{start_battle, Battle},
+    chat::ChatBroadcast,
+    logged_send, ClientList, ServerConfig,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ChallengeRequest {
+    Issue(NetworkID),
+    /// The challenger being answered, and whether the duel was accepted.
+    Respond(NetworkID, bool),
+    Cancel(NetworkID),
+}
+
+/// A challenge message sent by the client at the given address.
+pub struct ChallengeMessage(pub SocketAddr, pub ChallengeRequest);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct PendingChallenge {
+    challenger: NetworkID,
+    target: NetworkID,
+    issued_at: Instant,
+}
+
+impl PendingChallenge {
+    fn involves(&self, id: NetworkID) -> bool {
+        self.challenger == id || self.target == id
+    }
+}
+
+/// Every challenge that has been issued but not yet answered. A
+/// player may challenge several others at once, but each of them
+/// only once.
+#[derive(Default)]
+pub struct PendingChallenges(Vec<PendingChallenge>);
+
+impl PendingChallenges {
+    fn contains(&self, challenger: NetworkID, target: NetworkID) -> bool {
+        self.0
+            .iter()
+            .any(|challenge| challenge.challenger == challenger && challenge.target == target)
+    }
+
+    fn take(&mut self, challenger: NetworkID, target: NetworkID) -> Option<PendingChallenge> {
+        let index = self.0.iter().position(|challenge| {
+            challenge.challenger == challenger && challenge.target == target
+        })?;
+        Some(self.0.remove(index))
+    }
+
+    /// Remove and return every challenge matching the predicate.
+    fn take_where(
+        &mut self,
+        predicate: impl Fn(&PendingChallenge) -> bool,
+    ) -> Vec<PendingChallenge> {
+        let (taken, kept) = self.0.drain(..).partition(predicate);
+        self.0 = kept;
+        taken
+    }
+
+    fn take_expired(&mut self, now: Instant, timeout: Duration) -> Vec<PendingChallenge> {
+        self.take_where(|challenge| now.duration_since(challenge.issued_at) >= timeout)
+    }
+}
+
+/// Tell whichever of the two players are still here what became of
+/// their challenge.
+fn resolve(
+    challenge: PendingChallenge,
+    result: ChallengeResult,
+    clients: &ClientList,
+    sender: &mut Sender<Packet>,
+) {
+    let msg = ServerMessage::ChallengeResolved {
+        challenger: challenge.challenger,
+        target: challenge.target,
+        result,
+    };
+
+    [challenge.challenger, challenge.target]
+        .iter()
+        .filter_map(|id| clients.get_by_netid(*id))
+        .for_each(|(addr, _)| logged_send(sender, msg.to_packet(*addr)));
+}
+
+fn send_notice(text: impl Into<String>, addr: SocketAddr, sender: &mut Sender<Packet>) {
+    let msg = ServerMessage::notice(text);
+    logged_send(sender, msg.to_packet(addr));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn issue_challenge(
+    addr: SocketAddr,
+    challenger: NetworkID,
+    target: NetworkID,
+    now: Instant,
+    clients: &ClientList,
+    pending: &mut PendingChallenges,
+    sender: &mut Sender<Packet>,
+    commands: &mut CommandBuffer,
+) {
+    let Some((_, challenger_info)) = clients.get_by_netid(challenger) else {
+        return;
+    };
+    let Some((target_addr, target_info)) = clients.get_by_netid(target) else {
+        error!("Challenged an entity that doesn't exist. {target:?}");
+        send_notice("That player is no longer online.", addr, sender);
+        return;
+    };
+
+    let refusal = if challenger == target {
+        Some("You cannot challenge yourself.".to_string())
+    } else if challenger_info.mode == ClientMode::Battle {
+        Some("You are already in a duel.".to_string())
+    } else if challenger_info.mode == ClientMode::Spectating {
+        Some("You cannot challenge anyone while watching a duel.".to_string())
+    } else if target_info.mode == ClientMode::Battle {
+        Some(format!("{} is already in a duel.", target_info.username))
+    } else if target_info.mode == ClientMode::Spectating {
+        Some(format!("{} is watching a duel.", target_info.username))
+    } else if pending.contains(challenger, target) {
+        Some(format!(
+            "You have already challenged {}.",
+            target_info.username
+        ))
+    } else {
+        None
+    };
+    if let Some(refusal) = refusal {
+        send_notice(refusal, addr, sender);
+        return;
+    }
+
+    pending.0.push(PendingChallenge {
+        challenger,
+        target,
+        issued_at: now,
+    });
+
+    let msg = ServerMessage::PassAlongChallenge(challenger);
+    logged_send(sender, msg.to_packet(*target_addr));
+
+    let text = format!(
+        "{} has challenged {} to a duel!",
+        challenger_info.username, target_info.username
+    );
+    commands.push((ChatBroadcast(ServerMessage::notice(text)),));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn respond_to_challenge(
+    addr: SocketAddr,
+    challenger: NetworkID,
+    target: NetworkID,
+    accepted: bool,
+    clients: &mut ClientList,
+    pending: &mut PendingChallenges,
+    sender: &mut Sender<Packet>,
+    commands: &mut CommandBuffer,
+) {
+    let Some(challenge) = pending.take(challenger, target) else {
+        send_notice("That challenge is no longer open.", addr, sender);
+        return;
+    };
+
+    if !accepted {
+        resolve(challenge, ChallengeResult::Declined, clients, sender);
+        return;
+    }
+
+    let challenger_info = clients
+        .get_by_netid(challenger)
+        .map(|(_, info)| info.clone());
+    let target_info = clients.get_by_netid(target).map(|(_, info)| info.clone());
+    let (Some(challenger_info), Some(target_info)) = (challenger_info, target_info) else {
+        resolve(challenge, ChallengeResult::Cancelled, clients, sender);
+        return;
+    };
+    // Either of them may have started watching a duel since.
+    if challenger_info.mode != ClientMode::Overworld || target_info.mode != ClientMode::Overworld {
+        resolve(challenge, ChallengeResult::Cancelled, clients, sender);
+        return;
+    }
+
+    resolve(challenge, ChallengeResult::Accepted, clients, sender);
+
+    // Nobody can fight two duels at once, so every other challenge
+    // either of them was part of is off.
+    pending
+        .take_where(|other| other.involves(challenger) || other.involves(target))
+        .into_iter()
+        .for_each(|other| resolve(other, ChallengeResult::Cancelled, clients, sender));
+
+    let battle = Battle::new(
+        (challenger_info.player_id, &challenger_info.username),
+        (target_info.player_id, &target_info.username),
+    );
+    start_battle(battle, clients, sender, commands);
+}
+
+/// Apply every challenge message sent this tick, then let any
+/// challenge that has gone unanswered for too long lapse.
+#[system]
+pub fn handle_challenges(
+    request_query: &mut Query<(Entity, &ChallengeMessage)>,
+    world: &mut SubWorld,
+    #[resource] sender: &mut Sender<Packet>,
+    #[resource] clients: &mut ClientList,
+    #[resource] pending: &mut PendingChallenges,
+    #[resource] config: &ServerConfig,
+    commands: &mut CommandBuffer,
+) {
+    let now = Instant::now();
+    let requests: Vec<(SocketAddr, ChallengeRequest)> = request_query
+        .iter(world)
+        .map(|(message_entity, request)| {
+            commands.remove(*message_entity);
+            (request.0, request.1)
+        })
+        .collect();
+
+    requests.into_iter().for_each(|(addr, request)| {
+        let Some(player_id) = clients.addr_map.get(&addr).map(|info| info.player_id) else {
+            return;
+        };
+
+        match request {
+            ChallengeRequest::Issue(target) => issue_challenge(
+                addr, player_id, target, now, clients, pending, sender, commands,
+            ),
+            ChallengeRequest::Respond(challenger, accepted) => respond_to_challenge(
+                addr, challenger, player_id, accepted, clients, pending, sender, commands,
+            ),
+            ChallengeRequest::Cancel(target) => match pending.take(player_id, target) {
+                Some(challenge) => resolve(challenge, ChallengeResult::Cancelled, clients, sender),
+                None => send_notice("You have not challenged that player.", addr, sender),
+            },
+        }
+    });
+
+    pending
+        .take_expired(now, config.challenge_timeout())
+        .into_iter()
+        .for_each(|challenge| resolve(challenge, ChallengeResult::Expired, clients, sender));
+
+    // Anyone who left takes their challenges with them.
+    pending
+        .take_where(|challenge| {
+            clients.get_by_netid(challenge.challenger).is_none()
+                || clients.get_by_netid(challenge.target).is_none()
+        })
+        .into_iter()
+        .for_each(|challenge| resolve(challenge, ChallengeResult::Cancelled, clients, sender));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: NetworkID = NetworkID::new(1);
+    const BOB: NetworkID = NetworkID::new(2);
+    const CAROL: NetworkID = NetworkID::new(3);
+
+    #[test]
+    fn test_challenges_expire() {
+        let start = Instant::now();
+        let challenge = |challenger, target, secs| PendingChallenge {
+            challenger,
+            target,
+            issued_at: start + Duration::from_secs(secs),
+        };
+
+        let mut pending = PendingChallenges::default();
+        pending.0.push(challenge(ALICE, BOB, 0));
+        pending.0.push(challenge(CAROL, BOB, 20));
+
+        let now = start + Duration::from_secs(30);
+        let expired = pending.take_expired(now, Duration::from_secs(30));
+
+        assert_eq!(expired, [challenge(ALICE, BOB, 0)]);
+        assert!(!pending.contains(ALICE, BOB));
+        assert!(pending.contains(CAROL, BOB));
+        assert!(!pending.contains(BOB, CAROL));
+    }
+}
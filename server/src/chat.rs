@@ -0,0 +1,143 @@
+use std::{collections::VecDeque, net::SocketAddr, time::Instant};
+
+use common::messages::{ChatHistoryEntry, ServerMessage};
+use crossbeam_channel::Sender;
+use laminar::Packet;
+use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, Query};
+
+use crate::{logged_send, ClientList, ServerConfig};
+
+/// A chat message or notice meant for every connected player. These
+/// are sent at the end of the tick, once everyone who joined during
+/// it has been sent the history from before they arrived.
+pub struct ChatBroadcast(pub ServerMessage);
+
+/// Send the recent chat history, followed by the message of the day,
+/// to the client at the given address.
+pub struct SendChatHistory(pub SocketAddr);
+
+/// The most recent global chat and notices, oldest first, along with
+/// when each was said.
+pub struct ChatHistory {
+    entries: VecDeque<(Instant, ChatHistoryEntry)>,
+    capacity: usize,
+}
+
+impl ChatHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Remember a broadcast message if it is one players would want to
+    /// catch up on.
+    fn record(&mut self, msg: &ServerMessage) {
+        let ServerMessage::SendMessage(channel, author, text) = msg else {
+            return;
+        };
+
+        let entry = ChatHistoryEntry {
+            age_secs: 0,
+            channel: *channel,
+            author: author.clone(),
+            text: text.clone(),
+        };
+        self.entries.push_back((Instant::now(), entry));
+
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// The history as it should be sent right now.
+    fn entries(&self) -> Vec<ChatHistoryEntry> {
+        self.entries
+            .iter()
+            .map(|(said_at, entry)| ChatHistoryEntry {
+                age_secs: said_at.elapsed().as_secs(),
+                ..entry.clone()
+            })
+            .collect()
+    }
+}
+
+#[system]
+pub fn send_chat_history(
+    request_query: &mut Query<(Entity, &SendChatHistory)>,
+    world: &mut SubWorld,
+    #[resource] sender: &mut Sender<Packet>,
+    #[resource] history: &ChatHistory,
+    #[resource] config: &ServerConfig,
+    commands: &mut CommandBuffer,
+) {
+    request_query
+        .iter(world)
+        .for_each(|(message_entity, request)| {
+            commands.remove(*message_entity);
+
+            if !history.entries.is_empty() {
+                let msg = ServerMessage::ChatHistory(history.entries());
+                logged_send(sender, msg.to_packet(request.0));
+            }
+
+            if let Some(motd) = &config.motd {
+                let msg = ServerMessage::notice(motd.clone());
+                logged_send(sender, msg.to_packet(request.0));
+            }
+        });
+}
+
+#[system]
+pub fn broadcast_chat(
+    request_query: &mut Query<(Entity, &ChatBroadcast)>,
+    world: &mut SubWorld,
+    #[resource] sender: &mut Sender<Packet>,
+    #[resource] clients: &ClientList,
+    #[resource] history: &mut ChatHistory,
+    commands: &mut CommandBuffer,
+) {
+    request_query
+        .iter(world)
+        .for_each(|(message_entity, broadcast)| {
+            commands.remove(*message_entity);
+
+            history.record(&broadcast.0);
+            clients.all_addresses().iter().for_each(|addr| {
+                logged_send(sender, broadcast.0.to_packet(*addr));
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use common::messages::ChatChannel;
+
+    use super::*;
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = ChatHistory::new(2);
+        ["one", "two", "three"].iter().for_each(|text| {
+            history.record(&ServerMessage::notice(*text));
+        });
+        history.record(&ServerMessage::Emote(
+            "Alice".to_string(),
+            "waves".to_string(),
+        ));
+
+        let texts: Vec<(ChatChannel, String, u64)> = history
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.channel, entry.text, entry.age_secs))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                (ChatChannel::System, "two".to_string(), 0),
+                (ChatChannel::System, "three".to_string(), 0)
+            ]
+        );
+    }
+}
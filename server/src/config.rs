@@ -0,0 +1,181 @@
+use std::{
+    fmt::{self, Display},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use common::validation::ProfanityAction;
+use serde::Deserialize;
+
+use crate::traffic::RateLimits;
+
+/// Everything about a server instance that can be changed without
+/// rebuilding it. Every field is optional in a config file and
+/// falls back to its default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    pub tick_rate: u32,
+    /// Seconds without hearing from a client before it is dropped.
+    pub idle_timeout_secs: u64,
+    pub max_players: usize,
+    /// Shown to every player right after they connect.
+    pub motd: Option<String>,
+    /// A whitespace separated list of disallowed words. The built in
+    /// list is used if this is not set.
+    pub word_filter_path: Option<PathBuf>,
+    /// Whether chat messages with disallowed words are masked or
+    /// rejected.
+    pub profanity_action: ProfanityAction,
+    pub reserved_names: Vec<String>,
+    /// Where bans and mutes are loaded from on startup and saved to
+    /// whenever they change. They are forgotten when the server stops
+    /// if this is not set.
+    pub moderation_path: Option<PathBuf>,
+    /// How far away, in world units, players can hear each other on
+    /// the say channel.
+    pub say_radius: f32,
+    /// How many global chat messages and notices are kept to show
+    /// players when they join.
+    pub chat_history_length: usize,
+    /// Seconds a duel challenge waits for an answer before it lapses.
+    pub challenge_timeout_secs: u64,
+    /// Seconds a duelist has to act before they forfeit the duel.
+    pub turn_timeout_secs: u64,
+    /// Where duel ratings are loaded from on startup and saved to
+    /// after every duel. Everyone starts afresh each run if this is
+    /// not set.
+    pub ratings_path: Option<PathBuf>,
+    /// How many players are listed on the leaderboard.
+    pub leaderboard_size: usize,
+    pub rate_limits: RateLimits,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let reserved_names = ["SERVER", "SYSTEM", "ADMIN", "MODERATOR"];
+
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 27008,
+            tick_rate: 60,
+            idle_timeout_secs: 60,
+            max_players: 64,
+            motd: None,
+            word_filter_path: None,
+            profanity_action: ProfanityAction::default(),
+            reserved_names: reserved_names.iter().map(|name| name.to_string()).collect(),
+            moderation_path: None,
+            say_radius: 200.0,
+            chat_history_length: 50,
+            challenge_timeout_secs: 30,
+            turn_timeout_secs: 60,
+            ratings_path: None,
+            leaderboard_size: 10,
+            rate_limits: RateLimits::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Listen on the loopback interface on any free port, so
+    /// several servers can run side by side in tests.
+    pub fn loopback() -> Self {
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 0,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::from_toml(&text)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        toml::from_str(text).map_err(ConfigError::Parse)
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind_address, self.port)
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.tick_rate.max(1) as f64)
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_secs)
+    }
+
+    pub fn challenge_timeout(&self) -> Duration {
+        Duration::from_secs(self.challenge_timeout_secs)
+    }
+
+    pub fn turn_timeout(&self) -> Duration {
+        Duration::from_secs(self.turn_timeout_secs)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "Could not read the config file. {err}"),
+            Self::Parse(err) => write!(f, "The config file is invalid. {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traffic::MessageCategory;
+
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config = ServerConfig::from_toml(
+            "port = 27010\nmotd = \"Welcome!\"\nprofanity_action = \"reject\"",
+        )
+        .unwrap();
+
+        assert_eq!(config.port, 27010);
+        assert_eq!(config.motd.as_deref(), Some("Welcome!"));
+        assert_eq!(config.profanity_action, ProfanityAction::Reject);
+        assert_eq!(config.tick_rate, ServerConfig::default().tick_rate);
+    }
+
+    #[test]
+    fn test_rate_limits_can_be_overridden() {
+        let config = ServerConfig::from_toml(
+            "[rate_limits]\nban_secs = 120\n\n[rate_limits.per_category]\nchat = 3",
+        )
+        .unwrap();
+        let defaults = RateLimits::default();
+
+        assert_eq!(config.rate_limits.ban_secs, 120);
+        assert_eq!(config.rate_limits.window_secs, defaults.window_secs);
+        assert_eq!(config.rate_limits.per_category[&MessageCategory::Chat], 3);
+        assert_eq!(
+            config.rate_limits.per_category[&MessageCategory::Movement],
+            defaults.per_category[&MessageCategory::Movement]
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        let result = ServerConfig::from_toml("prot = 27010");
+        assert!(matches!(result, Err(ConfigError::Parse(_))));
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use common::{
+    math::Vec2,
+    messages::{DisconnectReason, ServerMessage},
+    validation::{usernames_match, WordFilter},
+};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use laminar::{Config, ErrorKind, Packet, Socket};
+use legion::{EntityStore, Resources, Schedule, World};
+use log::info;
+
+use crate::{
+    admin::{AdminCommand, AdminReplies, Moderation},
+    build_schedule,
+    challenge::PendingChallenges,
+    chat::ChatHistory,
+    logged_send,
+    rating::Ratings,
+    traffic::TrafficMonitor,
+    ClientList, ContentRules, NetworkedEntities, Position, ReservedNames, ServerConfig,
+    ShutdownHandle,
+};
+
+fn server_socket_config(config: &ServerConfig) -> Config {
+    Config {
+        idle_connection_timeout: config.idle_timeout(),
+        ..Default::default()
+    }
+}
+
+/// A bound server that only advances when told to. Ticking it by
+/// hand lets tests step client and server in lockstep and look at
+/// the server's state in between.
+pub struct ServerHandle {
+    socket: Socket,
+    world: World,
+    resources: Resources,
+    schedule: Schedule,
+    tick_duration: Duration,
+    moderation_path: Option<PathBuf>,
+    ratings_path: Option<PathBuf>,
+    shutdown: ShutdownHandle,
+    admin_commands: Sender<AdminCommand>,
+    admin_replies: Receiver<String>,
+}
+
+impl ServerHandle {
+    /// Bind a new server. Nothing is processed until it is ticked.
+    pub fn start(config: ServerConfig) -> Result<Self, ErrorKind> {
+        let socket = Socket::bind_with_config(config.socket_addr(), server_socket_config(&config))?;
+
+        let word_filter = match &config.word_filter_path {
+            Some(path) => WordFilter::new(&std::fs::read_to_string(path)?),
+            None => WordFilter::default(),
+        };
+
+        let moderation = match &config.moderation_path {
+            Some(path) if path.exists() => Moderation::load(path)?,
+            _ => Moderation::default(),
+        };
+
+        let ratings = match &config.ratings_path {
+            Some(path) if path.exists() => Ratings::load(path)?,
+            _ => Ratings::default(),
+        };
+
+        let shutdown = ShutdownHandle::default();
+        let (admin_commands, admin_command_receiver) = unbounded();
+        let (admin_reply_sender, admin_replies) = unbounded();
+
+        let mut resources = Resources::default();
+        resources.insert(socket.get_packet_sender());
+        resources.insert(socket.get_event_receiver());
+        resources.insert(ClientList::new());
+        resources.insert(NetworkedEntities(HashMap::new()));
+        resources.insert(ContentRules {
+            reserved_names: ReservedNames(config.reserved_names.clone()),
+            word_filter,
+            profanity_action: config.profanity_action,
+        });
+        resources.insert(TrafficMonitor::new(config.rate_limits.clone()));
+        resources.insert(ChatHistory::new(config.chat_history_length));
+        resources.insert(PendingChallenges::default());
+        resources.insert(moderation);
+        resources.insert(ratings);
+        resources.insert(shutdown.clone());
+        resources.insert(admin_command_receiver);
+        resources.insert(AdminReplies(admin_reply_sender));
+
+        let tick_duration = config.tick_duration();
+        let moderation_path = config.moderation_path.clone();
+        let ratings_path = config.ratings_path.clone();
+        resources.insert(config);
+
+        Ok(Self {
+            socket,
+            world: World::default(),
+            resources,
+            schedule: build_schedule(),
+            tick_duration,
+            moderation_path,
+            ratings_path,
+            shutdown,
+            admin_commands,
+            admin_replies,
+        })
+    }
+
+    /// Start a server on a background thread, ticking at its
+    /// configured rate until it is stopped.
+    pub fn spawn(config: ServerConfig) -> Result<BackgroundServer, ErrorKind> {
+        let (started_sender, started_receiver) = bounded(1);
+
+        // Resources cannot be sent between threads, so the server has to be built on its own.
+        let thread = thread::spawn(move || {
+            let handle = match ServerHandle::start(config) {
+                Ok(handle) => handle,
+                Err(err) => {
+                    let _ = started_sender.send(Err(err));
+                    return Ok(());
+                }
+            };
+
+            let started = (
+                handle.local_addr(),
+                handle.shutdown_handle(),
+                handle.admin_command_sender(),
+                handle.admin_reply_receiver(),
+            );
+            let _ = started_sender.send(Ok(started));
+
+            handle.run()
+        });
+
+        let (local_addr, shutdown, admin_commands, admin_replies) = started_receiver
+            .recv()
+            .expect("The server thread always reports whether it started.")?;
+
+        Ok(BackgroundServer {
+            local_addr,
+            shutdown,
+            admin_commands,
+            admin_replies,
+            thread,
+        })
+    }
+
+    /// The address the server is actually listening on, which is
+    /// useful when binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.socket
+            .local_addr()
+            .expect("A bound socket always has an address.")
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Commands sent here are run at the start of the next tick.
+    pub fn admin_command_sender(&self) -> Sender<AdminCommand> {
+        self.admin_commands.clone()
+    }
+
+    /// What each admin command did, as text to show whoever sent it.
+    pub fn admin_reply_receiver(&self) -> Receiver<String> {
+        self.admin_replies.clone()
+    }
+
+    /// Handle everything that has arrived since the last tick and
+    /// send out the results.
+    pub fn tick(&mut self) {
+        // The socket is polled here rather than on its own thread so that
+        // everything queued before shutting down can be flushed before returning.
+        self.socket.manual_poll(Instant::now());
+        self.schedule.execute(&mut self.world, &mut self.resources);
+        self.socket.manual_poll(Instant::now());
+    }
+
+    /// Tick at the configured rate until a shutdown is requested.
+    pub fn run(mut self) -> Result<(), ErrorKind> {
+        while !self.shutdown.is_requested() {
+            self.tick();
+            thread::sleep(self.tick_duration);
+        }
+
+        self.stop()
+    }
+
+    /// Disconnect every client and save anything that should outlive
+    /// the server.
+    pub fn stop(mut self) -> Result<(), ErrorKind> {
+        info!("Shutting down...");
+
+        self.notify_shutdown();
+        self.socket.manual_poll(Instant::now());
+
+        if let (Some(path), Some(moderation)) =
+            (&self.moderation_path, self.resources.get::<Moderation>())
+        {
+            moderation.save(path)?;
+        }
+        if let (Some(path), Some(ratings)) = (&self.ratings_path, self.resources.get::<Ratings>()) {
+            ratings.save(path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Let every connected client know the server is going away, so they
+    /// do not have to wait to time out.
+    fn notify_shutdown(&mut self) {
+        let (Some(mut sender), Some(clients)) = (
+            self.resources.get_mut::<Sender<Packet>>(),
+            self.resources.get::<ClientList>(),
+        ) else {
+            return;
+        };
+
+        let msg = ServerMessage::DisconnectClient(DisconnectReason::ServerShutdown);
+        clients.all_addresses().iter().for_each(|addr| {
+            let msg_packet = msg.to_packet(*addr);
+            logged_send(&mut sender, msg_packet);
+        });
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn connected_usernames(&self) -> Vec<String> {
+        self.resources
+            .get::<ClientList>()
+            .map(|clients| {
+                clients
+                    .addr_map
+                    .values()
+                    .map(|info| info.username.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Where the server thinks the named player is.
+    pub fn player_position(&self, username: &str) -> Option<Vec2> {
+        let clients = self.resources.get::<ClientList>()?;
+        let networked_entities = self.resources.get::<NetworkedEntities>()?;
+
+        let info = clients
+            .addr_map
+            .values()
+            .find(|info| usernames_match(&info.username, username))?;
+        let (e, _) = networked_entities.0.get(&info.player_id)?;
+        let entry = self.world.entry_ref(*e).ok()?;
+        let position = entry.get_component::<Position>().ok()?;
+
+        Some(position.0)
+    }
+}
+
+/// A server ticking on its own thread.
+pub struct BackgroundServer {
+    local_addr: SocketAddr,
+    shutdown: ShutdownHandle,
+    admin_commands: Sender<AdminCommand>,
+    admin_replies: Receiver<String>,
+    thread: JoinHandle<Result<(), ErrorKind>>,
+}
+
+impl BackgroundServer {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    pub fn admin_command_sender(&self) -> Sender<AdminCommand> {
+        self.admin_commands.clone()
+    }
+
+    pub fn admin_reply_receiver(&self) -> Receiver<String> {
+        self.admin_replies.clone()
+    }
+
+    /// Shut the server down and wait for it to finish.
+    pub fn stop(self) -> Result<(), ErrorKind> {
+        self.shutdown.shutdown();
+        self.wait()
+    }
+
+    /// Wait for the server to be shut down some other way.
+    pub fn wait(self) -> Result<(), ErrorKind> {
+        self.thread.join().expect("The server thread panicked.")
+    }
+}
@@ -1,59 +1,86 @@
+mod admin;
+mod battle;
+mod challenge;
+mod chat;
+mod config;
+mod handle;
 mod message_handling;
-
-use std::{collections::HashMap, net::SocketAddr, thread, time::Duration};
+mod rating;
+mod traffic;
+
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 use common::{
-    messages::{ClientMessage, InfoRequestType, InfoSendType, ServerMessage},
-    GameArchetype, NetworkID, PLAY_AREA_SIZE,
+    commands::ServerCommand,
+    math::Vec2,
+    messages::{
+        ChatChannel, ClientMessage, EntitySnapshot, InfoRequestType, InfoSendType, ServerMessage,
+    },
+    movement::{apply_movement, MoveInput},
+    validation::{normalize_username, usernames_match, ProfanityAction, WordFilter},
+    ClientMode, GameArchetype, NetworkID,
 };
 use crossbeam_channel::{Receiver, Sender};
-use laminar::{Config, ErrorKind, Packet, Socket, SocketEvent};
+use laminar::{ErrorKind, Packet, SocketEvent};
 use legion::{
-    system, systems::CommandBuffer, world::SubWorld, Entity, EntityStore, Query, Resources,
-    Schedule, World,
+    system, systems::CommandBuffer, world::SubWorld, Entity, EntityStore, Query, Schedule,
+};
+use log::{error, info, warn};
+
+pub use admin::{AdminCommand, BanTarget, ParseCommandError, ADMIN_USAGE};
+pub use config::{ConfigError, ServerConfig};
+pub use handle::{BackgroundServer, ServerHandle};
+pub use traffic::{MessageCategory, RateLimits};
+
+use crate::{
+    admin::{run_admin_commands_system, Moderation},
+    battle::{
+        handle_spectators_system, resolve_battles_system, BattleActionRequest, SpectateMessage,
+        SpectateRequest,
+    },
+    challenge::{handle_challenges_system, ChallengeMessage, ChallengeRequest},
+    chat::{broadcast_chat_system, send_chat_history_system, ChatBroadcast},
+    message_handling::{
+        check_ban, check_capacity, check_chat_message, check_not_muted, check_protocol_version,
+        check_username, handle_command, handle_connect_message, handle_disconnect, handle_whisper,
+    },
+    rating::{send_leaderboards_system, LeaderboardRequest},
+    traffic::{TrafficMonitor, Verdict},
 };
-use log::{error, info};
-
-use crate::message_handling::handle_connect_message;
-
-fn server_socket_config() -> Config {
-    Config {
-        idle_connection_timeout: Duration::from_secs(60),
-        ..Default::default()
-    }
-}
-
-pub fn server() -> Result<(), ErrorKind> {
-    let addr = "0.0.0.0:27008";
-    println!("Listening at port 27008");
-    let mut socket = Socket::bind_with_config(addr, server_socket_config())?;
-
-    let (sender, receiver) = (socket.get_packet_sender(), socket.get_event_receiver());
-    let _thread = thread::spawn(move || socket.start_polling());
-
-    let clients = ClientList::new();
-
-    let mut world = World::default();
-    let mut resources = Resources::default();
-
-    resources.insert(sender);
-    resources.insert(receiver);
-    resources.insert(clients);
-    resources.insert(NetworkedEntities(HashMap::new()));
-
-    let mut schedule = build_schedule();
 
-    loop {
-        schedule.execute(&mut world, &mut resources);
-        thread::sleep(Duration::from_millis(16));
-    }
+/// Run a server on the current thread until it is shut down. Use
+/// [`ServerHandle`] to tick it yourself or to send it admin commands.
+pub fn server(config: ServerConfig) -> Result<(), ErrorKind> {
+    ServerHandle::start(config)?.run()
 }
 
 fn build_schedule() -> Schedule {
     Schedule::builder()
         .add_system(parse_incoming_packets_system(0))
+        .add_system(run_admin_commands_system())
         .flush()
         .add_system(send_player_info_system())
+        .add_system(send_join_snapshots_system())
+        .add_system(apply_move_inputs_system())
+        .add_system(handle_challenges_system())
+        .flush()
+        .add_system(handle_spectators_system())
+        .add_system(resolve_battles_system())
+        .add_system(send_leaderboards_system())
+        .add_system(deliver_local_chat_system())
+        .add_system(broadcast_position_snapshots_system(0))
+        .add_system(send_chat_history_system())
+        .add_system(broadcast_chat_system())
         .build()
 }
 
@@ -82,7 +109,7 @@ impl ClientList {
 struct ClientInfo {
     username: String,
     player_id: NetworkID,
-    challenge_target: Option<NetworkID>,
+    mode: ClientMode,
 }
 
 impl ClientInfo {
@@ -90,13 +117,46 @@ impl ClientInfo {
         Self {
             username: username.to_string(),
             player_id,
-            challenge_target: None,
+            mode: ClientMode::Overworld,
         }
     }
 }
 
+/// Lets a running server be stopped from anywhere, such as another
+/// thread or a signal handler. The server finishes its current tick,
+/// says goodbye to every client and then returns.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle(Arc<AtomicBool>);
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
 pub struct NetworkedEntities(HashMap<NetworkID, (Entity, GameArchetype)>);
 
+/// Usernames nobody may log in with, compared the same way as
+/// usernames already in use.
+pub struct ReservedNames(pub Vec<String>);
+
+impl ReservedNames {
+    fn contains(&self, username: &str) -> bool {
+        self.0.iter().any(|name| usernames_match(name, username))
+    }
+}
+
+/// Everything usernames and chat messages are checked against.
+pub struct ContentRules {
+    pub reserved_names: ReservedNames,
+    pub word_filter: WordFilter,
+    pub profanity_action: ProfanityAction,
+}
+
 /// Send the provided packet and write to log in the event of an
 /// error.
 fn logged_send(sender: &mut Sender<Packet>, packet: Packet) {
@@ -107,92 +167,105 @@ fn logged_send(sender: &mut Sender<Packet>, packet: Packet) {
     }
 }
 
+/// Write a file by way of a temporary one next to it, so a crash
+/// partway through cannot leave it truncated.
+fn write_atomically(path: &Path, text: &str) -> io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    std::fs::write(&temp_path, text)?;
+    std::fs::rename(&temp_path, path)
+}
+
 #[system]
+#[allow(clippy::too_many_arguments)]
 fn parse_incoming_packets(
     #[state] next_id: &mut usize,
     #[resource] receiver: &mut Receiver<SocketEvent>,
     #[resource] sender: &mut Sender<Packet>,
     #[resource] clients: &mut ClientList,
     #[resource] networked_entities: &mut NetworkedEntities,
+    #[resource] content_rules: &ContentRules,
+    #[resource] config: &ServerConfig,
+    #[resource] traffic: &mut TrafficMonitor,
+    #[resource] moderation: &Moderation,
     commands: &mut CommandBuffer,
 ) {
+    let now = Instant::now();
+    traffic.forget_idle(now);
+
     receiver.try_iter().for_each(|event|  {
         match event {
-            //TODO: Handle Timeouts
+            SocketEvent::Timeout(addr) => {
+                info!("Connection to {addr} timed out");
+                handle_disconnect(addr, clients, sender, networked_entities, commands);
+            }
             SocketEvent::Disconnect(addr) => {
-                if let Some(client_info) = clients.addr_map.remove(&addr) {
-                    info!("{} has disconnected", client_info.username);
-
-                    let id = client_info.player_id;
-
-                    let chat_message = ServerMessage::SendMessage("SERVER".to_string(), format!("{} has disconnected.", client_info.username));
-                    let delete_message = ServerMessage::DespawnNetworkedEntity(id);
-
-                    clients.all_addresses().iter().for_each(|addr| {
-                        let chat_packet = Packet::reliable_unordered(*addr, chat_message.to_payload());
-                        let delete_packet = Packet::reliable_unordered(*addr, delete_message.to_payload());
-
-                        logged_send(sender, chat_packet);
-                        logged_send(sender, delete_packet);
-                    });
-                }
+                handle_disconnect(addr, clients, sender, networked_entities, commands);
             }
             SocketEvent::Packet(packet) => {
-                let msg = ClientMessage::from_payload(packet.payload());
-
-                if msg.is_err() {
-                    error!("Received an invalid message from ip {}. This may be a result of malicious activity.\nErr: {}", packet.addr(), msg.as_ref().unwrap_err());
+                let addr = packet.addr();
+                if traffic.is_blocked(addr, now) {
+                    return;
                 }
 
-                match msg.unwrap() {
-                    ClientMessage::Connect(username) => {
-                        println!("Connecting client: {username}...");
-                        handle_connect_message(
-                            &username,
-                            next_id,
-                            clients,
-                            &packet,
-                            sender,
-                            networked_entities,
-                            commands,
-                        );
+                let msg = match ClientMessage::from_payload(packet.payload()) {
+                    Ok(msg) => msg,
+                    Err(err) => {
+                        error!("Received an invalid message from ip {}. This may be a result of malicious activity.\nErr: {}", addr, err);
+                        if traffic.record_invalid_packet(addr, now) == Verdict::Ban {
+                            warn!("Banning {addr} after {} invalid packets.", traffic.total_invalid_packets(addr));
+                            handle_disconnect(addr, clients, sender, networked_entities, commands);
+                        }
+                        return;
                     }
-                    ClientMessage::MoveTo(pos) => {
-                        if let Some(client_info) = clients.addr_map.get(&packet.addr()) {
-                            let mut clamped_pos = pos;
-                            clamped_pos.x = clamped_pos.x.clamp(0.0, PLAY_AREA_SIZE.x);
-                            clamped_pos.y = clamped_pos.y.clamp(0.0, PLAY_AREA_SIZE.y);
+                };
+
+                match traffic.check_message(addr, MessageCategory::of(&msg), now) {
+                    Verdict::Allow => {}
+                    Verdict::Drop => return,
+                    Verdict::Ban => {
+                        warn!("Banning {addr} for repeatedly exceeding rate limits.");
+                        handle_disconnect(addr, clients, sender, networked_entities, commands);
+                        return;
+                    }
+                }
 
-                            let msg = ServerMessage::SendNetworkedEntityInfo(
-                                client_info.player_id,
-                                InfoSendType::Position(clamped_pos),
+                match msg {
+                    ClientMessage::Connect { username, protocol_version, build } => {
+                        info!("{username} ({build}) is attempting to connect...");
+                        let username = normalize_username(&username);
+                        if check_ban(&moderation.bans, &username, &packet, sender)
+                            && check_protocol_version(protocol_version, &packet, sender)
+                            && check_capacity(clients, config.max_players, &packet, sender)
+                            && check_username(&username, clients, content_rules, &packet, sender)
+                        {
+                            handle_connect_message(
+                                &username,
+                                config.tick_rate,
+                                next_id,
+                                clients,
+                                &packet,
+                                sender,
+                                networked_entities,
+                                commands,
                             );
-                            clients
-                                .all_addresses()
-                                .iter()
-                                .filter(|addr| **addr != packet.addr())
-                                .for_each(|addr| {
-                                    let msg_packet = Packet::unreliable(*addr, msg.to_payload());
-                                    sender.send(msg_packet).expect("This should send.");
-                                });
-
-                            // Lock the active player in if they try to go out of bounds.
-                            if clamped_pos != pos {
-                                let msg_packet =
-                                    Packet::unreliable(packet.addr(), msg.to_payload());
-                                sender.send(msg_packet).expect("This should send.");
+                        }
+                    }
+                    ClientMessage::Move(input) => {
+                        if let Some(client_info) = clients.addr_map.get(&packet.addr()) {
+                            // Players stay where they are while they are in a duel.
+                            if client_info.mode == ClientMode::Overworld {
+                                commands.push((MoveRequest(client_info.player_id, input),));
                             }
                         } else {
                             error!("Someone attempted to send a move packet without having properly connected...");
                         }
                     }
                     ClientMessage::Disconnect => {
-                        if let Some(client_info) = clients.addr_map.remove(&packet.addr()) {
-                            println!("{} has disconnected", client_info.username);
-                        }
+                        handle_disconnect(packet.addr(), clients, sender, networked_entities, commands);
                     }
                     ClientMessage::RequestArchetype(id) => {
-                        if clients.addr_map.get(&packet.addr()).is_some() {
+                        if clients.addr_map.contains_key(&packet.addr()) {
                             if let Some(_archetype) = networked_entities.0.get(&id) {
                                 let msg = ServerMessage::SpawnNetworkedEntity(
                                     id,
@@ -201,7 +274,7 @@ fn parse_incoming_packets(
                                 );
 
                                 let msg_packet =
-                                    Packet::unreliable(packet.addr(), msg.to_payload());
+                                    msg.to_packet(packet.addr());
                                 sender.send(msg_packet).expect("This should send.");
                             } else {
                                 error!("Requested an entity ID that doesn't exist. {id:?}");
@@ -210,22 +283,83 @@ fn parse_incoming_packets(
                             error!("Someone attempted to send a move packet without having properly connected...");
                         }
                     }
-                    ClientMessage::SendMessage(msg) => {
+                    ClientMessage::SendMessage(channel, msg) => {
                         if let Some(client_info) = clients.addr_map.get(&packet.addr()) {
-                            info!("CHAT - {}: {msg}", client_info.username.to_owned());
-                            let msg =
-                                ServerMessage::SendMessage(client_info.username.to_owned(), msg);
-                            clients.all_addresses().iter().for_each(|addr| {
-                                let msg_packet = Packet::unreliable(*addr, msg.to_payload());
-                                sender.send(msg_packet).expect("This should send.");
-                            });
+                            if !check_not_muted(&client_info.username, &moderation.muted, &packet, sender) {
+                                return;
+                            }
+                            let Some(msg) = check_chat_message(&msg, content_rules, &packet, sender) else {
+                                return;
+                            };
+
+                            match channel {
+                                ChatChannel::Global => {
+                                    info!("CHAT - {}: {msg}", client_info.username.to_owned());
+                                    let msg = ServerMessage::SendMessage(
+                                        channel,
+                                        client_info.username.to_owned(),
+                                        msg,
+                                    );
+                                    commands.push((ChatBroadcast(msg),));
+                                }
+                                ChatChannel::Say => {
+                                    commands.push((SayRequest(client_info.player_id, msg),));
+                                }
+                                ChatChannel::System => {
+                                    warn!("{} tried to send a system message. This may be a result of malicious activity.", client_info.username);
+                                }
+                            }
                         } else {
                             error!("Someone attempted to send a message packet without having properly connected...");
                         }
                     }
+                    ClientMessage::Whisper(target, text) => {
+                        if let Some(client_info) = clients.addr_map.get(&packet.addr()) {
+                            if !check_not_muted(&client_info.username, &moderation.muted, &packet, sender) {
+                                return;
+                            }
+                            if let Some(text) = check_chat_message(&text, content_rules, &packet, sender) {
+                                handle_whisper(&client_info.username, &target, text, clients, &packet, sender);
+                            }
+                        } else {
+                            error!("Someone attempted to whisper without having properly connected...");
+                        }
+                    }
+                    ClientMessage::Command(command) => {
+                        if let Some(client_info) = clients.addr_map.get(&packet.addr()) {
+                            // Muted players may still look around, just not speak.
+                            let speaks = !matches!(command, ServerCommand::Who);
+                            if speaks && !check_not_muted(&client_info.username, &moderation.muted, &packet, sender) {
+                                return;
+                            }
+
+                            let command = match command {
+                                ServerCommand::Emote(text) => match check_chat_message(&text, content_rules, &packet, sender) {
+                                    Some(text) => ServerCommand::Emote(text),
+                                    None => return,
+                                },
+                                command => command,
+                            };
+                            handle_command(&client_info.username, command, clients, &packet, sender, commands);
+                        } else {
+                            error!("Someone attempted to run a command without having properly connected...");
+                        }
+                    }
+                    ClientMessage::BattleAction(action) => {
+                        if let Some(client_info) = clients.addr_map.get(&packet.addr()) {
+                            if client_info.mode == ClientMode::Battle {
+                                commands.push((BattleActionRequest(packet.addr(), action),));
+                            } else {
+                                let msg = ServerMessage::notice("You are not in a duel.");
+                                logged_send(sender, msg.to_packet(packet.addr()));
+                            }
+                        } else {
+                            error!("Someone attempted a battle action without having properly connected...");
+                        }
+                    }
                     ClientMessage::RequestEntityInfo(id, info) => {
-                        if clients.addr_map.get(&packet.addr()).is_some() {
-                            if networked_entities.0.get(&id).is_some() {
+                        if clients.addr_map.contains_key(&packet.addr()) {
+                            if networked_entities.0.contains_key(&id) {
                                 info!("Marking an entity to send its info to a client with ID {id:?}");
                                 commands.push((SendInfoRequest(id, packet.addr(), info),));
                             } else {
@@ -236,46 +370,25 @@ fn parse_incoming_packets(
                         }
                     }
                     ClientMessage::IssueChallenge(target) => {
-                        if let Some(sender_info) = clients.addr_map.get(&packet.addr())  {
-                            if let Some((addr, info)) = clients.addr_map.iter().find(|(_, info)| info.player_id == target )  {
-                                let msg = ServerMessage::PassAlongChallenge(sender_info.player_id);
-                                let challenge_packet = Packet::reliable_unordered(*addr, msg.to_payload());
-                                logged_send(sender, challenge_packet);
-
-                                let chat_msg = ServerMessage::SendMessage("SERVER".to_string(), format!("{} has challenged {} to a duel!", sender_info.username, info.username));
-                                clients.all_addresses().iter().for_each(|addr| {
-                                    let chat_packet = Packet::reliable_unordered(*addr, chat_msg.to_payload());
-                                    logged_send(sender, chat_packet);
-                                });
-
-                                clients.addr_map.get_mut(&packet.addr()).unwrap().challenge_target = Some(target);
-                            } else {
-                                error!("Challenged an entity that doesn't exist. {target:?}");
-                            }
-                        } else {
-                            error!("Someone requested an entity's info without being properly connected.");
-                        }
+                        push_challenge_message(packet.addr(), ChallengeRequest::Issue(target), clients, commands);
                     }
-                    ClientMessage::RespondToChallenge(target, _accepted) => {
-                        let sender_info = clients.addr_map.get(&packet.addr());
-                        if let Some(sender_info) = sender_info {
-                            let mut success = false;
-                            if let Some((target_addr, target_info)) = clients.get_by_netid(target) {
-                                if target_info.challenge_target == Some(sender_info.player_id) {
-                                    success = true;
-                                    clients.addr_map.get_mut(&target_addr.clone()).unwrap().challenge_target = None;
-                                    clients.addr_map.get_mut(&packet.addr()).unwrap().challenge_target = None;
-                                    println!("Two players have begun a duel!");
-                                }
-                            }
-
-                            if !success {
-                                let err_msg = ServerMessage::SendMessage("SERVER".to_string(), "Duel Cancelled -- The other player may have disconnected or challenged someone else.".to_string());
-                                let error_message_packet = Packet::reliable_unordered(packet.addr(), err_msg.to_payload());
-                                logged_send(sender, error_message_packet);
-                            }
+                    ClientMessage::RespondToChallenge(challenger, accepted) => {
+                        push_challenge_message(packet.addr(), ChallengeRequest::Respond(challenger, accepted), clients, commands);
+                    }
+                    ClientMessage::CancelChallenge(target) => {
+                        push_challenge_message(packet.addr(), ChallengeRequest::Cancel(target), clients, commands);
+                    }
+                    ClientMessage::SpectateDuel(target) => {
+                        push_spectate_message(packet.addr(), SpectateRequest::Watch(target), clients, commands);
+                    }
+                    ClientMessage::StopSpectating => {
+                        push_spectate_message(packet.addr(), SpectateRequest::Stop, clients, commands);
+                    }
+                    ClientMessage::RequestLeaderboard => {
+                        if clients.addr_map.contains_key(&packet.addr()) {
+                            commands.push((LeaderboardRequest(packet.addr()),));
                         } else {
-                            error!("Someone tried to respond to a challenge without being connected!");
+                            error!("Someone requested the leaderboard without being properly connected.");
                         }
                     }
                 }
@@ -285,10 +398,52 @@ fn parse_incoming_packets(
     });
 }
 
+/// Challenges are handled by their own system, which keeps track of
+/// the ones still waiting for an answer.
+fn push_challenge_message(
+    addr: SocketAddr,
+    request: ChallengeRequest,
+    clients: &ClientList,
+    commands: &mut CommandBuffer,
+) {
+    if clients.addr_map.contains_key(&addr) {
+        commands.push((ChallengeMessage(addr, request),));
+    } else {
+        error!("Someone sent a challenge message without having properly connected...");
+    }
+}
+
+/// Spectators are handled alongside the battles they are watching.
+fn push_spectate_message(
+    addr: SocketAddr,
+    request: SpectateRequest,
+    clients: &ClientList,
+    commands: &mut CommandBuffer,
+) {
+    if clients.addr_map.contains_key(&addr) {
+        commands.push((SpectateMessage(addr, request),));
+    } else {
+        error!("Someone sent a spectating message without having properly connected...");
+    }
+}
+
 /// Send the requested info of the specified entity to the client
 /// at the given address.
 struct SendInfoRequest(NetworkID, SocketAddr, InfoRequestType);
+/// Send every networked entity except the given one to the client
+/// at the given address.
+struct SendJoinSnapshot(SocketAddr, NetworkID);
 struct PlayerInfo(String);
+/// A movement input waiting to be applied to the given player.
+struct MoveRequest(NetworkID, MoveInput);
+/// Something the given player said out loud, to be heard by
+/// whoever is close enough.
+struct SayRequest(NetworkID, String);
+/// The sequence number of the last movement input applied to a player.
+struct LastProcessedMove(Option<u32>);
+
+/// The authoritative position of a networked entity in the play area.
+pub struct Position(pub Vec2);
 
 #[system]
 #[read_component(SendInfoRequest)]
@@ -312,7 +467,7 @@ fn send_player_info(
                             InfoSendType::Identity(info.0.clone()),
                         );
 
-                        let packet = Packet::unreliable(send_request.1, msg.to_payload());
+                        let packet = msg.to_packet(send_request.1);
 
                         sender.send(packet).expect("This should send.");
                     }
@@ -324,3 +479,162 @@ fn send_player_info(
             commands.remove(*message_entity);
         });
 }
+
+#[system]
+fn send_join_snapshots(
+    request_query: &mut Query<(Entity, &SendJoinSnapshot)>,
+    entity_query: &mut Query<(&NetworkID, &GameArchetype, &PlayerInfo, &Position)>,
+    world: &mut SubWorld,
+    #[resource] sender: &mut Sender<Packet>,
+    commands: &mut CommandBuffer,
+) {
+    request_query
+        .iter(world)
+        .for_each(|(message_entity, request)| {
+            let snapshot: Vec<EntitySnapshot> = entity_query
+                .iter(world)
+                .filter(|(id, _, _, _)| **id != request.1)
+                .map(|(id, archetype, info, pos)| EntitySnapshot {
+                    id: *id,
+                    archetype: *archetype,
+                    name: info.0.clone(),
+                    position: pos.0,
+                })
+                .collect();
+
+            let msg = ServerMessage::JoinSnapshot(snapshot);
+            let packet = msg.to_packet(request.0);
+            logged_send(sender, packet);
+
+            commands.remove(*message_entity);
+        });
+}
+
+#[system]
+#[write_component(Position)]
+#[write_component(LastProcessedMove)]
+fn apply_move_inputs(
+    request_query: &mut Query<(Entity, &MoveRequest)>,
+    world: &mut SubWorld,
+    #[resource] networked_entities: &NetworkedEntities,
+    commands: &mut CommandBuffer,
+) {
+    let mut requests: Vec<(NetworkID, MoveInput)> = request_query
+        .iter(world)
+        .map(|(message_entity, request)| {
+            commands.remove(*message_entity);
+            (request.0, request.1)
+        })
+        .collect();
+
+    // Inputs are sent unreliably, so they have to be put back in order.
+    requests.sort_by_key(|(_, input)| input.sequence);
+
+    requests.iter().for_each(|(id, input)| {
+        if let Some((e, _)) = networked_entities.0.get(id) {
+            if let Ok(mut entry) = world.entry_mut(*e) {
+                let is_newer = match entry.get_component::<LastProcessedMove>() {
+                    Ok(LastProcessedMove(Some(last))) => input.sequence > *last,
+                    Ok(LastProcessedMove(None)) => true,
+                    Err(_) => false,
+                };
+
+                if !is_newer {
+                    return;
+                }
+
+                if let Ok(pos) = entry.get_component_mut::<Position>() {
+                    pos.0 = apply_movement(pos.0, input.direction);
+                }
+                if let Ok(last) = entry.get_component_mut::<LastProcessedMove>() {
+                    last.0 = Some(input.sequence);
+                }
+            }
+        }
+    });
+}
+
+/// Pass what players say along to everyone within earshot of
+/// where the server thinks they are standing.
+#[system]
+fn deliver_local_chat(
+    request_query: &mut Query<(Entity, &SayRequest)>,
+    speaker_query: &mut Query<(&NetworkID, &PlayerInfo, &Position)>,
+    world: &mut SubWorld,
+    #[resource] sender: &mut Sender<Packet>,
+    #[resource] clients: &ClientList,
+    #[resource] config: &ServerConfig,
+    commands: &mut CommandBuffer,
+) {
+    request_query
+        .iter(world)
+        .for_each(|(message_entity, request)| {
+            commands.remove(*message_entity);
+
+            let Some((speaker, origin)) = speaker_query
+                .iter(world)
+                .find(|(id, _, _)| **id == request.0)
+                .map(|(_, info, pos)| (info.0.clone(), pos.0))
+            else {
+                return;
+            };
+
+            info!("SAY - {speaker}: {}", request.1);
+            let msg = ServerMessage::SendMessage(ChatChannel::Say, speaker, request.1.clone());
+            speaker_query
+                .iter(world)
+                .filter(|(_, _, pos)| pos.0.distance_to(origin) <= config.say_radius)
+                .filter_map(|(id, _, _)| clients.get_by_netid(*id))
+                .for_each(|(addr, _)| {
+                    logged_send(sender, msg.to_packet(*addr));
+                });
+        });
+}
+
+/// How many server ticks pass between each position snapshot.
+const SNAPSHOT_INTERVAL_TICKS: u64 = 3;
+
+/// Periodically send every client the current position of every
+/// networked entity.
+#[system]
+#[read_component(LastProcessedMove)]
+fn broadcast_position_snapshots(
+    #[state] tick: &mut u64,
+    query: &mut Query<(&NetworkID, &Position)>,
+    world: &mut SubWorld,
+    #[resource] sender: &mut Sender<Packet>,
+    #[resource] clients: &ClientList,
+    #[resource] networked_entities: &NetworkedEntities,
+) {
+    *tick += 1;
+    if !tick.is_multiple_of(SNAPSHOT_INTERVAL_TICKS) {
+        return;
+    }
+
+    let positions: Vec<(NetworkID, Vec2)> =
+        query.iter(world).map(|(id, pos)| (*id, pos.0)).collect();
+
+    if positions.is_empty() {
+        return;
+    }
+
+    let msg = ServerMessage::PositionSnapshot(*tick, positions);
+    clients.addr_map.iter().for_each(|(addr, info)| {
+        let msg_packet = msg.to_packet(*addr);
+        logged_send(sender, msg_packet);
+
+        // Let the client know how much of its own movement has been applied.
+        if let Some((e, _)) = networked_entities.0.get(&info.player_id) {
+            if let Ok(entry) = world.entry_ref(*e) {
+                if let (Ok(LastProcessedMove(Some(sequence))), Ok(pos)) = (
+                    entry.get_component::<LastProcessedMove>(),
+                    entry.get_component::<Position>(),
+                ) {
+                    let ack = ServerMessage::AcknowledgeMove(*sequence, pos.0);
+                    let ack_packet = ack.to_packet(*addr);
+                    logged_send(sender, ack_packet);
+                }
+            }
+        }
+    });
+}
@@ -1,13 +1,261 @@
-use common::{messages::ServerMessage, validation::validate_username, GameArchetype, NetworkID};
+use common::{
+    commands::ServerCommand,
+    messages::{DisconnectReason, ServerMessage, PROTOCOL_VERSION},
+    validation::{usernames_match, validate_chat_message, validate_username_with},
+    GameArchetype, NetworkID, PLAY_AREA_SIZE,
+};
+use std::net::SocketAddr;
+
 use crossbeam_channel::Sender;
 use laminar::Packet;
 use legion::systems::CommandBuffer;
-use log::info;
+use log::{info, warn};
+use rand::Rng;
+
+use crate::{
+    admin::{BanList, MutedPlayers},
+    chat::{ChatBroadcast, SendChatHistory},
+    logged_send, ClientInfo, ClientList, ContentRules, LastProcessedMove, NetworkedEntities,
+    PlayerInfo, Position, SendJoinSnapshot,
+};
+
+/// Check that neither a connecting client's address nor its
+/// username has been banned, rejecting it if either has.
+pub fn check_ban(
+    ban_list: &BanList,
+    username: &str,
+    packet: &Packet,
+    sender: &Sender<Packet>,
+) -> bool {
+    if !ban_list.is_banned(packet.addr().ip(), username) {
+        return true;
+    }
+
+    info!("Rejecting banned client {username} at {}", packet.addr());
+
+    let msg = ServerMessage::DisconnectClient(DisconnectReason::Banned);
+    let msg_packet = msg.to_packet(packet.addr());
+    sender.send(msg_packet).expect("This should send.");
+
+    false
+}
+
+/// Check that a connecting client speaks the same protocol as the
+/// server, rejecting it if not.
+pub fn check_protocol_version(
+    protocol_version: u32,
+    packet: &Packet,
+    sender: &Sender<Packet>,
+) -> bool {
+    if protocol_version == PROTOCOL_VERSION {
+        return true;
+    }
+
+    info!("Rejecting client with protocol version {protocol_version}, expected {PROTOCOL_VERSION}");
+
+    let msg = ServerMessage::DisconnectClient(DisconnectReason::IncompatibleVersion {
+        server_version: PROTOCOL_VERSION,
+    });
+    let msg_packet = msg.to_packet(packet.addr());
+    sender.send(msg_packet).expect("This should send.");
+
+    false
+}
+
+/// Check that there is room on the server for another player,
+/// rejecting the connecting client if not.
+pub fn check_capacity(
+    clients: &ClientList,
+    max_players: usize,
+    packet: &Packet,
+    sender: &Sender<Packet>,
+) -> bool {
+    if clients.addr_map.len() < max_players {
+        return true;
+    }
+
+    info!("Rejecting client at {}, the server is full", packet.addr());
+
+    let msg = ServerMessage::DisconnectClient(DisconnectReason::ServerFull);
+    let msg_packet = msg.to_packet(packet.addr());
+    sender.send(msg_packet).expect("This should send.");
+
+    false
+}
+
+/// Check that a connecting client's username is valid and not in
+/// use or reserved, rejecting it if not.
+pub fn check_username(
+    username: &str,
+    clients: &ClientList,
+    rules: &ContentRules,
+    packet: &Packet,
+    sender: &Sender<Packet>,
+) -> bool {
+    let rejection = if validate_username_with(username, &rules.word_filter).is_err() {
+        Some(DisconnectReason::InvalidUsername)
+    } else if rules.reserved_names.contains(username) {
+        Some(DisconnectReason::ReservedUsername)
+    } else if clients
+        .addr_map
+        .values()
+        .any(|info| usernames_match(&info.username, username))
+    {
+        Some(DisconnectReason::UsernameTaken)
+    } else {
+        None
+    };
+
+    if let Some(reason) = rejection {
+        // Disallowed username. Send a rejection message.
+        info!("Rejecting username {username}: {reason:?}");
+
+        let msg = ServerMessage::DisconnectClient(reason);
+        let msg_packet = msg.to_packet(packet.addr());
+        sender.send(msg_packet).expect("This should send.");
+        return false;
+    }
+
+    true
+}
+
+/// Check that a client is allowed to chat, letting it know if not.
+pub fn check_not_muted(
+    username: &str,
+    muted_players: &MutedPlayers,
+    packet: &Packet,
+    sender: &mut Sender<Packet>,
+) -> bool {
+    if !muted_players.contains(username) {
+        return true;
+    }
+
+    info!("Dropping a message from muted player {username}");
+
+    let msg = ServerMessage::notice("You have been muted.");
+    logged_send(sender, msg.to_packet(packet.addr()));
+
+    false
+}
 
-use crate::{ClientInfo, ClientList, NetworkedEntities, PlayerInfo};
+/// Clean up a chat message before it goes anywhere, letting the
+/// author know if it cannot be sent at all.
+pub fn check_chat_message(
+    text: &str,
+    rules: &ContentRules,
+    packet: &Packet,
+    sender: &mut Sender<Packet>,
+) -> Option<String> {
+    match validate_chat_message(text, &rules.word_filter, rules.profanity_action) {
+        Ok(text) => Some(text),
+        Err(err) => {
+            info!("Dropping a message from {}: {err:?}", packet.addr());
+
+            let msg = ServerMessage::notice(format!("Your message was not sent. {err}"));
+            logged_send(sender, msg.to_packet(packet.addr()));
+            None
+        }
+    }
+}
+
+/// Pass a private message along to a single player. The author gets
+/// a copy so they can see it was delivered, or an error if nobody
+/// by that name is online.
+pub fn handle_whisper(
+    author: &str,
+    target: &str,
+    text: String,
+    clients: &ClientList,
+    packet: &Packet,
+    sender: &mut Sender<Packet>,
+) {
+    let recipient = clients
+        .addr_map
+        .iter()
+        .find(|(_, info)| usernames_match(&info.username, target));
+
+    let Some((recipient_addr, recipient_info)) = recipient else {
+        let msg = ServerMessage::notice(format!("{target} is not online."));
+        logged_send(sender, msg.to_packet(packet.addr()));
+        return;
+    };
+
+    info!("WHISPER - {author} to {}: {text}", recipient_info.username);
+    let msg = ServerMessage::Whisper {
+        author: author.to_string(),
+        recipient: recipient_info.username.clone(),
+        text,
+    };
+
+    logged_send(sender, msg.to_packet(*recipient_addr));
+    if *recipient_addr != packet.addr() {
+        logged_send(sender, msg.to_packet(packet.addr()));
+    }
+}
 
+/// Run a chat command that needs the server's help, such as listing
+/// who is online or rolling dice everyone can trust.
+pub fn handle_command(
+    author: &str,
+    command: ServerCommand,
+    clients: &ClientList,
+    packet: &Packet,
+    sender: &mut Sender<Packet>,
+    commands: &mut CommandBuffer,
+) {
+    let (msg, recipients) = match command {
+        ServerCommand::Who => {
+            let mut names: Vec<&str> = clients
+                .addr_map
+                .values()
+                .map(|info| info.username.as_str())
+                .collect();
+            names.sort_unstable_by_key(|name| name.to_lowercase());
+
+            let text = format!("{} online: {}", names.len(), names.join(", "));
+            let msg = ServerMessage::notice(text);
+            (msg, vec![packet.addr()])
+        }
+        ServerCommand::Emote(text) => {
+            info!("EMOTE - {author} {text}");
+            let msg = ServerMessage::Emote(author.to_string(), text);
+            (msg, clients.all_addresses())
+        }
+        ServerCommand::Roll(roll) => {
+            if !roll.is_reasonable() {
+                warn!("{author} asked to roll {roll}, which no client should send.");
+                return;
+            }
+
+            let mut rng = rand::thread_rng();
+            let rolls: Vec<u32> = (0..roll.count)
+                .map(|_| rng.gen_range(1..=roll.sides))
+                .collect();
+            let total: u32 = rolls.iter().sum();
+
+            let text = if rolls.len() > 1 {
+                let rolls: Vec<String> = rolls.iter().map(u32::to_string).collect();
+                format!(
+                    "{author} rolled {roll} and got {total} ({}).",
+                    rolls.join(", ")
+                )
+            } else {
+                format!("{author} rolled {roll} and got {total}.")
+            };
+            commands.push((ChatBroadcast(ServerMessage::notice(text)),));
+            return;
+        }
+    };
+
+    recipients.into_iter().for_each(|addr| {
+        logged_send(sender, msg.to_packet(addr));
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn handle_connect_message(
     username: &str,
+    tick_rate: u32,
     next_id: &mut usize,
     clients: &mut ClientList,
     packet: &Packet,
@@ -15,66 +263,84 @@ pub fn handle_connect_message(
     networked_entities: &mut NetworkedEntities,
     commands: &mut CommandBuffer,
 ) {
-    info!("{username} is attempting to connect...");
-
-    // TODO: Also check for duplicates
-    let validation_result = validate_username(username);
-    if validation_result.is_ok() {
-        info!("Connection accepted!");
-        let txt = format!("{username} has connected");
-
-        // Connect user successfully
-        let player_id = NetworkID::new(*next_id);
-        *next_id += 1;
-        clients
-            .addr_map
-            .insert(packet.addr(), ClientInfo::new(username, player_id));
-
-        let msg = ServerMessage::ConnectionAccepted;
-        let addr = packet.addr();
-        let msg_packet = Packet::reliable_unordered(addr, msg.to_payload());
-        sender.send(msg_packet).expect("This should send.");
+    info!("{username} has been accepted!");
+    let txt = format!("{username} has connected");
 
-        clients.all_addresses().iter().for_each(|addr| {
-            if *addr == packet.addr() {
-                // Spawn owned player
-                let msg = ServerMessage::SpawnNetworkedEntity(
-                    player_id,
-                    common::GameArchetype::Player,
-                    true,
-                );
-                let msg_packet = Packet::reliable_unordered(*addr, msg.to_payload());
-                sender.send(msg_packet).expect("This should send.");
-            } else {
-                // Spawn remote player
-                let msg = ServerMessage::SpawnNetworkedEntity(
-                    player_id,
-                    common::GameArchetype::Player,
-                    false,
-                );
-                let msg_packet = Packet::reliable_unordered(*addr, msg.to_payload());
-                sender.send(msg_packet).expect("This should send.");
-            }
-        });
+    // Connect user successfully
+    let player_id = NetworkID::new(*next_id);
+    *next_id += 1;
+    clients
+        .addr_map
+        .insert(packet.addr(), ClientInfo::new(username, player_id));
 
-        let e = commands.push((GameArchetype::Player, PlayerInfo(username.to_string())));
-        networked_entities
-            .0
-            .insert(player_id, (e, GameArchetype::Player));
+    let msg = ServerMessage::ConnectionAccepted { tick_rate };
+    let addr = packet.addr();
+    let msg_packet = msg.to_packet(addr);
+    sender.send(msg_packet).expect("This should send.");
 
-        let msg = ServerMessage::SendMessage("SERVER".to_string(), txt);
-        clients.all_addresses().iter().for_each(|addr| {
-            let msg_packet = Packet::unreliable(*addr, msg.to_payload());
+    clients.all_addresses().iter().for_each(|addr| {
+        if *addr == packet.addr() {
+            // Spawn owned player
+            let msg =
+                ServerMessage::SpawnNetworkedEntity(player_id, common::GameArchetype::Player, true);
+            let msg_packet = msg.to_packet(*addr);
             sender.send(msg_packet).expect("This should send.");
-        });
-    } else {
-        // Disallowed username. Send a rejection message.
-        info!("Rejecting Invalid Username");
+        } else {
+            // Spawn remote player
+            let msg = ServerMessage::SpawnNetworkedEntity(
+                player_id,
+                common::GameArchetype::Player,
+                false,
+            );
+            let msg_packet = msg.to_packet(*addr);
+            sender.send(msg_packet).expect("This should send.");
+        }
+    });
 
-        let msg =
-            ServerMessage::DisconnectClient(common::messages::DisconnectReason::InvalidUsername);
-        let addr = packet.addr();
-        let msg_packet = Packet::reliable_unordered(addr, msg.to_payload());
-        sender.send(msg_packet).expect("This should send.");
+    // Existing entities are only guaranteed to be in the world after the next flush,
+    // so the snapshot itself is built by a later system.
+    commands.push((SendJoinSnapshot(packet.addr(), player_id),));
+
+    let e = commands.push((
+        GameArchetype::Player,
+        PlayerInfo(username.to_string()),
+        player_id,
+        Position(PLAY_AREA_SIZE * 0.5),
+        LastProcessedMove(None),
+    ));
+    networked_entities
+        .0
+        .insert(player_id, (e, GameArchetype::Player));
+
+    commands.push((SendChatHistory(packet.addr()),));
+    commands.push((ChatBroadcast(ServerMessage::notice(txt)),));
+}
+
+/// Remove a client and its player entity from the game and let
+/// everyone else know they have left. Clean disconnects, timeouts
+/// and logouts all end up here.
+pub fn handle_disconnect(
+    addr: SocketAddr,
+    clients: &mut ClientList,
+    sender: &mut Sender<Packet>,
+    networked_entities: &mut NetworkedEntities,
+    commands: &mut CommandBuffer,
+) {
+    if let Some(client_info) = clients.addr_map.remove(&addr) {
+        info!("{} has disconnected", client_info.username);
+
+        let id = client_info.player_id;
+        if let Some((e, _)) = networked_entities.0.remove(&id) {
+            commands.remove(e);
+        }
+
+        let chat_message =
+            ServerMessage::notice(format!("{} has disconnected.", client_info.username));
+        commands.push((ChatBroadcast(chat_message),));
+
+        let delete_message = ServerMessage::DespawnNetworkedEntity(id);
+        clients.all_addresses().iter().for_each(|addr| {
+            logged_send(sender, delete_message.to_packet(*addr));
+        });
     }
 }
@@ -0,0 +1,190 @@
+use std::{io, net::SocketAddr, path::Path};
+
+use common::{
+    battle::{Leaderboard, LeaderboardEntry},
+    messages::ServerMessage,
+    validation::usernames_match,
+};
+use crossbeam_channel::Sender;
+use laminar::Packet;
+use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, Query};
+use serde::{Deserialize, Serialize};
+
+use crate::{logged_send, write_atomically, ClientList, ServerConfig};
+
+/// The rating every player starts from.
+pub const DEFAULT_RATING: i32 = 1200;
+/// The most a rating can move after a single duel.
+const K_FACTOR: f64 = 32.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerRating {
+    pub name: String,
+    pub rating: i32,
+    pub wins: u32,
+    pub losses: u32,
+}
+
+impl PlayerRating {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            rating: DEFAULT_RATING,
+            wins: 0,
+            losses: 0,
+        }
+    }
+}
+
+/// The Elo rating of everyone who has fought a duel, which outlives
+/// the server when it has somewhere to be saved.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ratings {
+    players: Vec<PlayerRating>,
+}
+
+impl Ratings {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text =
+            toml::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        write_atomically(path, &text)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PlayerRating> {
+        self.players
+            .iter()
+            .find(|player| usernames_match(&player.name, name))
+    }
+
+    fn get_or_insert(&mut self, name: &str) -> &mut PlayerRating {
+        let index = match self
+            .players
+            .iter()
+            .position(|player| usernames_match(&player.name, name))
+        {
+            Some(index) => index,
+            None => {
+                self.players.push(PlayerRating::new(name));
+                self.players.len() - 1
+            }
+        };
+        &mut self.players[index]
+    }
+
+    /// Update both players after a duel, returning how many points
+    /// changed hands.
+    pub fn record_duel(&mut self, winner: &str, loser: &str) -> i32 {
+        let winner_rating = self.get_or_insert(winner).rating;
+        let loser_rating = self.get_or_insert(loser).rating;
+
+        let expected = 1.0 / (1.0 + 10f64.powf((loser_rating - winner_rating) as f64 / 400.0));
+        let change = (K_FACTOR * (1.0 - expected)).round() as i32;
+
+        let winner = self.get_or_insert(winner);
+        winner.rating += change;
+        winner.wins += 1;
+
+        let loser = self.get_or_insert(loser);
+        loser.rating -= change;
+        loser.losses += 1;
+
+        change
+    }
+
+    /// Everyone, best first. Ties go to whoever has won more.
+    fn ranked(&self) -> Vec<LeaderboardEntry> {
+        let mut players: Vec<&PlayerRating> = self.players.iter().collect();
+        players.sort_by(|a, b| {
+            b.rating
+                .cmp(&a.rating)
+                .then(b.wins.cmp(&a.wins))
+                .then(a.name.cmp(&b.name))
+        });
+
+        players
+            .into_iter()
+            .enumerate()
+            .map(|(idx, player)| LeaderboardEntry {
+                rank: idx as u32 + 1,
+                name: player.name.clone(),
+                rating: player.rating,
+                wins: player.wins,
+                losses: player.losses,
+            })
+            .collect()
+    }
+
+    pub fn leaderboard(&self, size: usize, requester: &str) -> Leaderboard {
+        let ranked = self.ranked();
+        let own = ranked
+            .iter()
+            .find(|entry| usernames_match(&entry.name, requester))
+            .cloned();
+
+        Leaderboard {
+            top: ranked.into_iter().take(size).collect(),
+            own,
+        }
+    }
+}
+
+/// A request for the leaderboard from the client at the given
+/// address.
+pub struct LeaderboardRequest(pub SocketAddr);
+
+#[system]
+pub fn send_leaderboards(
+    request_query: &mut Query<(Entity, &LeaderboardRequest)>,
+    world: &mut SubWorld,
+    #[resource] sender: &mut Sender<Packet>,
+    #[resource] clients: &ClientList,
+    #[resource] ratings: &Ratings,
+    #[resource] config: &ServerConfig,
+    commands: &mut CommandBuffer,
+) {
+    request_query
+        .iter(world)
+        .for_each(|(message_entity, request)| {
+            commands.remove(*message_entity);
+
+            let Some(info) = clients.addr_map.get(&request.0) else {
+                return;
+            };
+
+            let leaderboard = ratings.leaderboard(config.leaderboard_size, &info.username);
+            let msg = ServerMessage::Leaderboard(leaderboard);
+            logged_send(sender, msg.to_packet(request.0));
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upsets_are_worth_more() {
+        let mut ratings = Ratings::default();
+
+        assert_eq!(ratings.record_duel("Alice", "Bob"), 16);
+        assert_eq!(ratings.get("alice").map(|alice| alice.rating), Some(1216));
+        assert_eq!(ratings.get("Bob").map(|bob| bob.rating), Some(1184));
+
+        assert!(ratings.record_duel("Alice", "Carol") < 16);
+        assert!(ratings.record_duel("Bob", "Alice") > 16);
+
+        let text = toml::to_string(&ratings).unwrap();
+        let loaded: Ratings = toml::from_str(&text).unwrap();
+        let board = loaded.leaderboard(2, "carol");
+
+        let names: Vec<&str> = board.top.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["Alice", "Bob"]);
+        assert_eq!(board.top[0].wins, 2);
+        assert_eq!(board.own.map(|own| own.rank), Some(3));
+    }
+}
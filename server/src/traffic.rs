@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use common::{messages::ClientMessage, movement::INPUTS_PER_SECOND};
+use serde::{Deserialize, Deserializer};
+
+/// Groups of client messages that are rate limited separately.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageCategory {
+    Connect,
+    Movement,
+    Chat,
+    Challenge,
+    Battle,
+    EntityRequest,
+    Other,
+}
+
+impl MessageCategory {
+    pub fn of(msg: &ClientMessage) -> Self {
+        match msg {
+            ClientMessage::Connect { .. } => Self::Connect,
+            ClientMessage::Move(_) => Self::Movement,
+            ClientMessage::SendMessage(_, _)
+            | ClientMessage::Whisper(_, _)
+            | ClientMessage::Command(_) => Self::Chat,
+            ClientMessage::IssueChallenge(_)
+            | ClientMessage::RespondToChallenge(_, _)
+            | ClientMessage::CancelChallenge(_) => Self::Challenge,
+            ClientMessage::BattleAction(_)
+            | ClientMessage::SpectateDuel(_)
+            | ClientMessage::StopSpectating => Self::Battle,
+            ClientMessage::RequestArchetype(_) | ClientMessage::RequestEntityInfo(_, _) => {
+                Self::EntityRequest
+            }
+            ClientMessage::RequestLeaderboard | ClientMessage::Disconnect => Self::Other,
+        }
+    }
+}
+
+/// How much traffic each address may send. Read from the
+/// `[rate_limits]` section of the server config, where every field
+/// falls back to its default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    /// Seconds message counts are accumulated for before being reset.
+    /// The per-category limits are counted over this window, so they
+    /// should be changed along with it.
+    pub window_secs: u64,
+    /// The most messages of each category allowed per window.
+    /// Categories without an entry are not limited. Categories left
+    /// out of a config file keep their default limit.
+    #[serde(deserialize_with = "merge_with_default_limits")]
+    pub per_category: HashMap<MessageCategory, u32>,
+    /// The most undecodable packets allowed per window.
+    pub max_invalid_packets: u32,
+    /// Seconds an address is ignored for after exceeding a limit.
+    pub throttle_secs: u64,
+    /// How many times an address may be throttled before it is banned.
+    pub strikes_before_ban: u32,
+    pub ban_secs: u64,
+}
+
+fn merge_with_default_limits<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<MessageCategory, u32>, D::Error> {
+    let mut limits = RateLimits::default().per_category;
+    limits.extend(HashMap::<MessageCategory, u32>::deserialize(deserializer)?);
+    Ok(limits)
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        let window_secs = 5;
+        let per_category = HashMap::from([
+            (MessageCategory::Connect, 5),
+            // Clients send inputs at a fixed rate while a key is held. The
+            // headroom covers catching up after a slow frame.
+            (
+                MessageCategory::Movement,
+                INPUTS_PER_SECOND * window_secs as u32 * 2,
+            ),
+            (MessageCategory::Chat, 10),
+            (MessageCategory::Challenge, 10),
+            (MessageCategory::Battle, 20),
+            (MessageCategory::EntityRequest, 200),
+            (MessageCategory::Other, 20),
+        ]);
+
+        Self {
+            window_secs,
+            per_category,
+            max_invalid_packets: 10,
+            throttle_secs: 10,
+            strikes_before_ban: 3,
+            ban_secs: 60 * 15,
+        }
+    }
+}
+
+impl RateLimits {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    pub fn throttle_duration(&self) -> Duration {
+        Duration::from_secs(self.throttle_secs)
+    }
+
+    pub fn ban_duration(&self) -> Duration {
+        Duration::from_secs(self.ban_secs)
+    }
+}
+
+/// What should happen to a packet from a given address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Drop,
+    /// Drop the packet and disconnect the address, which has just been banned.
+    Ban,
+}
+
+#[derive(Default)]
+struct AddressRecord {
+    window_start: Option<Instant>,
+    message_counts: HashMap<MessageCategory, u32>,
+    invalid_packets: u32,
+    total_invalid_packets: u64,
+    strikes: u32,
+    last_strike: Option<Instant>,
+    throttled_until: Option<Instant>,
+    banned_until: Option<Instant>,
+}
+
+impl AddressRecord {
+    fn reset_window_if_expired(&mut self, window: Duration, now: Instant) {
+        let expired = match self.window_start {
+            Some(start) => now.duration_since(start) >= window,
+            None => true,
+        };
+
+        if expired {
+            self.window_start = Some(now);
+            self.message_counts.clear();
+            self.invalid_packets = 0;
+        }
+    }
+
+    fn is_blocked(&self, now: Instant) -> bool {
+        let banned = matches!(self.banned_until, Some(until) if now < until);
+        let throttled = matches!(self.throttled_until, Some(until) if now < until);
+        banned || throttled
+    }
+
+    fn has_recent_strikes(&self, limits: &RateLimits, now: Instant) -> bool {
+        matches!(self.last_strike, Some(last) if now.duration_since(last) < limits.ban_duration())
+    }
+
+    fn strike(&mut self, limits: &RateLimits, now: Instant) -> Verdict {
+        // Old offences are forgiven eventually.
+        if !self.has_recent_strikes(limits, now) {
+            self.strikes = 0;
+        }
+        self.strikes += 1;
+        self.last_strike = Some(now);
+
+        if self.strikes >= limits.strikes_before_ban {
+            self.strikes = 0;
+            self.banned_until = Some(now + limits.ban_duration());
+            Verdict::Ban
+        } else {
+            self.throttled_until = Some(now + limits.throttle_duration());
+            Verdict::Drop
+        }
+    }
+}
+
+/// Keeps track of how much and what kind of traffic each address
+/// sends, so misbehaving addresses can be throttled and banned
+/// before their packets reach game logic.
+pub struct TrafficMonitor {
+    limits: RateLimits,
+    records: HashMap<SocketAddr, AddressRecord>,
+}
+
+impl TrafficMonitor {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            records: HashMap::new(),
+        }
+    }
+
+    /// Whether every packet from this address should currently be ignored.
+    pub fn is_blocked(&self, addr: SocketAddr, now: Instant) -> bool {
+        matches!(self.records.get(&addr), Some(record) if record.is_blocked(now))
+    }
+
+    /// Count a packet that could not be decoded.
+    pub fn record_invalid_packet(&mut self, addr: SocketAddr, now: Instant) -> Verdict {
+        let record = self.records.entry(addr).or_default();
+        record.reset_window_if_expired(self.limits.window(), now);
+
+        record.invalid_packets += 1;
+        record.total_invalid_packets += 1;
+
+        if record.invalid_packets > self.limits.max_invalid_packets {
+            record.invalid_packets = 0;
+            record.strike(&self.limits, now)
+        } else {
+            Verdict::Drop
+        }
+    }
+
+    /// Count a decoded message and decide whether it may be handled.
+    pub fn check_message(
+        &mut self,
+        addr: SocketAddr,
+        category: MessageCategory,
+        now: Instant,
+    ) -> Verdict {
+        let record = self.records.entry(addr).or_default();
+        record.reset_window_if_expired(self.limits.window(), now);
+
+        let count = record.message_counts.entry(category).or_insert(0);
+        *count += 1;
+
+        match self.limits.per_category.get(&category) {
+            // Only strike once per window, the moment the limit is crossed.
+            Some(limit) if *count == limit + 1 => record.strike(&self.limits, now),
+            Some(limit) if *count > *limit => Verdict::Drop,
+            _ => Verdict::Allow,
+        }
+    }
+
+    pub fn total_invalid_packets(&self, addr: SocketAddr) -> u64 {
+        self.records
+            .get(&addr)
+            .map_or(0, |record| record.total_invalid_packets)
+    }
+
+    /// Forget addresses that have gone quiet and are not being punished.
+    pub fn forget_idle(&mut self, now: Instant) {
+        let limits = &self.limits;
+        self.records.retain(|_, record| {
+            let active = matches!(record.window_start, Some(start) if now.duration_since(start) < limits.window());
+            active || record.is_blocked(now) || record.has_recent_strikes(limits, now)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::movement::InputClock;
+
+    use super::*;
+
+    fn test_limits() -> RateLimits {
+        RateLimits {
+            window_secs: 1,
+            per_category: HashMap::from([(MessageCategory::Chat, 2)]),
+            max_invalid_packets: 2,
+            throttle_secs: 5,
+            strikes_before_ban: 2,
+            ban_secs: 60,
+        }
+    }
+
+    fn addr() -> SocketAddr {
+        "127.0.0.1:5000".parse().unwrap()
+    }
+
+    #[test]
+    fn test_messages_over_limit_are_dropped() {
+        let mut monitor = TrafficMonitor::new(test_limits());
+        let now = Instant::now();
+
+        let verdicts: Vec<Verdict> = (0..3)
+            .map(|_| monitor.check_message(addr(), MessageCategory::Chat, now))
+            .collect();
+
+        assert_eq!(verdicts, [Verdict::Allow, Verdict::Allow, Verdict::Drop]);
+        assert!(monitor.is_blocked(addr(), now));
+        assert!(!monitor.is_blocked(addr(), now + Duration::from_secs(6)));
+    }
+
+    #[test]
+    fn test_unlimited_categories_are_allowed() {
+        let mut monitor = TrafficMonitor::new(test_limits());
+        let now = Instant::now();
+
+        (0..100).for_each(|_| {
+            let verdict = monitor.check_message(addr(), MessageCategory::Movement, now);
+            assert_eq!(verdict, Verdict::Allow);
+        });
+    }
+
+    #[test]
+    fn test_moving_continuously_is_never_throttled() {
+        [30, 60, 144, 240].iter().for_each(|fps| {
+            let mut monitor = TrafficMonitor::new(RateLimits::default());
+            let mut clock = InputClock::default();
+            let start = Instant::now();
+            let frame_time = Duration::from_secs_f64(1.0 / *fps as f64);
+
+            (0..*fps * 60).for_each(|frame| {
+                let now = start + frame_time * frame;
+                (0..clock.tick(frame_time.as_secs_f64())).for_each(|_| {
+                    let verdict = monitor.check_message(addr(), MessageCategory::Movement, now);
+                    assert_eq!(verdict, Verdict::Allow, "Throttled at {fps} fps");
+                });
+            });
+        });
+    }
+
+    #[test]
+    fn test_counts_reset_each_window() {
+        let mut monitor = TrafficMonitor::new(test_limits());
+        let now = Instant::now();
+
+        monitor.check_message(addr(), MessageCategory::Chat, now);
+        monitor.check_message(addr(), MessageCategory::Chat, now);
+        let later = now + Duration::from_secs(2);
+        let verdict = monitor.check_message(addr(), MessageCategory::Chat, later);
+
+        assert_eq!(verdict, Verdict::Allow);
+    }
+
+    #[test]
+    fn test_repeat_offenders_are_banned() {
+        let mut monitor = TrafficMonitor::new(test_limits());
+        let now = Instant::now();
+
+        let first: Vec<Verdict> = (0..3)
+            .map(|_| monitor.record_invalid_packet(addr(), now))
+            .collect();
+        assert_eq!(first, [Verdict::Drop, Verdict::Drop, Verdict::Drop]);
+
+        let later = now + Duration::from_secs(6);
+        assert!(!monitor.is_blocked(addr(), later));
+        let second: Vec<Verdict> = (0..3)
+            .map(|_| monitor.record_invalid_packet(addr(), later))
+            .collect();
+
+        assert_eq!(second.last(), Some(&Verdict::Ban));
+        assert!(monitor.is_blocked(addr(), later + Duration::from_secs(30)));
+        assert_eq!(monitor.total_invalid_packets(addr()), 6);
+    }
+}
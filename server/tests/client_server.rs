@@ -0,0 +1,698 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use client::{functionality::DuelingClient, ClientEvent, ConnectionStatus, NetworkClient};
+use common::{
+    battle::{BattleAction, BattleEnd, ChallengeResult, Leaderboard},
+    commands::{DiceRoll, ServerCommand},
+    math::Vec2,
+    messages::{ChatChannel, DisconnectReason},
+    ClientMode, NetworkID,
+};
+use server::{AdminCommand, ServerConfig, ServerHandle};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tick the server and poll the clients until the condition holds,
+/// failing the test if it never does.
+fn tick_until(
+    server: &mut ServerHandle,
+    clients: &mut [&mut NetworkClient],
+    mut condition: impl FnMut(&ServerHandle, &[&mut NetworkClient]) -> bool,
+) {
+    let start = Instant::now();
+    while !condition(server, clients) {
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "Timed out waiting for a condition."
+        );
+
+        server.tick();
+        clients.iter_mut().for_each(|client| {
+            client.receive_messages().ok();
+        });
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn connect(server: &mut ServerHandle, username: &str) -> NetworkClient {
+    let mut client = NetworkClient::default();
+    client.connect_to(server.local_addr(), username).unwrap();
+
+    tick_until(server, &mut [&mut client], |_, clients| {
+        !matches!(clients[0].connection_status(), ConnectionStatus::Connecting)
+    });
+
+    client
+}
+
+#[test]
+fn test_client_connects_to_server() {
+    let mut server = ServerHandle::start(ServerConfig::loopback()).unwrap();
+    let client = connect(&mut server, "Alice");
+
+    assert!(matches!(
+        client.connection_status(),
+        ConnectionStatus::Connected
+    ));
+    assert_eq!(server.connected_usernames(), ["Alice"]);
+}
+
+#[test]
+fn test_duplicate_username_is_rejected() {
+    let mut server = ServerHandle::start(ServerConfig::loopback()).unwrap();
+    let _first = connect(&mut server, "Alice");
+    let second = connect(&mut server, "alice");
+
+    assert!(matches!(
+        second.connection_status(),
+        ConnectionStatus::Failed(DisconnectReason::UsernameTaken)
+    ));
+    assert_eq!(server.connected_usernames().len(), 1);
+}
+
+#[test]
+fn test_movement_and_chat_reach_the_server() {
+    let mut server = ServerHandle::start(ServerConfig::loopback()).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let mut bob = connect(&mut server, "Bob");
+    let start = server.player_position("Alice").unwrap();
+
+    alice.move_player(Vec2::new(1.0, 0.0)).unwrap();
+    alice
+        .send_chat_message(ChatChannel::Global, "Hello!")
+        .unwrap();
+
+    let events = bob.get_event_receiver();
+    let mut received_chat = false;
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |server, _| {
+        received_chat |= events.try_iter().any(|event| {
+            matches!(event, ClientEvent::MessageReceived(_, author, text) if author == "Alice" && text == "Hello!")
+        });
+        let moved = server
+            .player_position("Alice")
+            .is_some_and(|pos| pos.x > start.x);
+        received_chat && moved
+    });
+}
+
+#[test]
+fn test_invalid_movement_is_ignored() {
+    let mut server = ServerHandle::start(ServerConfig::loopback()).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let start = server.player_position("Alice").unwrap();
+
+    alice.move_player(Vec2::new(f32::NAN, f32::NAN)).unwrap();
+    alice.move_player(Vec2::new(f32::INFINITY, 0.0)).unwrap();
+    alice.move_player(Vec2::new(0.0, 1.0)).unwrap();
+
+    // Only the last input moves Alice.
+    tick_until(&mut server, &mut [&mut alice], |server, _| {
+        server
+            .player_position("Alice")
+            .is_some_and(|pos| pos != start)
+    });
+    let pos = server.player_position("Alice").unwrap();
+    assert_eq!(pos.x, start.x);
+    assert!(pos.y > start.y);
+}
+
+#[test]
+fn test_clients_are_told_when_the_server_stops() {
+    let server = ServerHandle::spawn(ServerConfig::loopback()).unwrap();
+    let mut client = NetworkClient::default();
+    client.connect_to(server.local_addr(), "Alice").unwrap();
+
+    let start = Instant::now();
+    while !matches!(client.connection_status(), ConnectionStatus::Connected) {
+        assert!(start.elapsed() < TIMEOUT, "Timed out connecting.");
+        client.receive_messages().unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    server.stop().unwrap();
+
+    let start = Instant::now();
+    while !matches!(
+        client.connection_status(),
+        ConnectionStatus::Failed(DisconnectReason::ServerShutdown)
+    ) {
+        assert!(start.elapsed() < TIMEOUT, "Never heard about the shutdown.");
+        client.receive_messages().unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn test_whispers_only_reach_their_recipient() {
+    let mut server = ServerHandle::start(ServerConfig::loopback()).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let mut bob = connect(&mut server, "Bob");
+    let mut carol = connect(&mut server, "Carol");
+
+    alice.send_whisper("bob", "Psst").unwrap();
+    alice.send_whisper("Dave", "Hello?").unwrap();
+
+    let whisper = ClientEvent::Whisper {
+        author: "Alice".to_string(),
+        recipient: "Bob".to_string(),
+        text: "Psst".to_string(),
+    };
+    let (alice_events, bob_events) = (alice.get_event_receiver(), bob.get_event_receiver());
+    let (mut alice_log, mut bob_log) = (Vec::new(), Vec::new());
+    tick_until(
+        &mut server,
+        &mut [&mut alice, &mut bob, &mut carol],
+        |_, _| {
+            alice_log.extend(alice_events.try_iter());
+            bob_log.extend(bob_events.try_iter());
+            let offline_error = alice_log.iter().any(|event| {
+                matches!(
+                    event,
+                    ClientEvent::MessageReceived(ChatChannel::System, _, text)
+                        if text.contains("Dave")
+                )
+            });
+            offline_error && alice_log.contains(&whisper) && bob_log.contains(&whisper)
+        },
+    );
+
+    let carol_heard = carol
+        .get_event_receiver()
+        .try_iter()
+        .any(|event| matches!(event, ClientEvent::Whisper { .. }));
+    assert!(!carol_heard);
+}
+
+#[test]
+fn test_server_commands() {
+    let mut server = ServerHandle::start(ServerConfig::loopback()).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let mut bob = connect(&mut server, "Bob");
+
+    alice.send_command(ServerCommand::Who).unwrap();
+    alice
+        .send_command(ServerCommand::Emote("waves".to_string()))
+        .unwrap();
+    alice
+        .send_command(ServerCommand::Roll(DiceRoll { count: 2, sides: 6 }))
+        .unwrap();
+
+    let bob_events = bob.get_event_receiver();
+    let mut bob_log = Vec::new();
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        bob_log.extend(bob_events.try_iter());
+        let rolled = bob_log.iter().any(|event| {
+            matches!(event, ClientEvent::MessageReceived(_, _, text) if text.starts_with("Alice rolled 2d6"))
+        });
+        rolled
+            && bob_log.contains(&ClientEvent::Emote(
+                "Alice".to_string(),
+                "waves".to_string(),
+            ))
+    });
+
+    let who = ClientEvent::MessageReceived(
+        ChatChannel::System,
+        "SERVER".to_string(),
+        "2 online: Alice, Bob".to_string(),
+    );
+    assert!(alice
+        .get_event_receiver()
+        .try_iter()
+        .any(|event| event == who));
+    assert!(!bob_log.contains(&who));
+}
+
+#[test]
+fn test_moderation_is_saved_straight_away() {
+    let moderation_path =
+        std::env::temp_dir().join(format!("shackle-moderation-{}.toml", std::process::id()));
+    let mut server = ServerHandle::start(ServerConfig {
+        moderation_path: Some(moderation_path.clone()),
+        ..ServerConfig::loopback()
+    })
+    .unwrap();
+
+    let admin_commands = server.admin_command_sender();
+    admin_commands
+        .send(AdminCommand::Mute("Mallory".to_string()))
+        .unwrap();
+    server.tick();
+    let saved = std::fs::read_to_string(&moderation_path).unwrap();
+    assert!(saved.contains("Mallory"));
+
+    admin_commands
+        .send(AdminCommand::Unmute("mallory".to_string()))
+        .unwrap();
+    server.tick();
+    let saved = std::fs::read_to_string(&moderation_path).unwrap();
+    std::fs::remove_file(&moderation_path).unwrap();
+    assert!(!saved.contains("Mallory"));
+}
+
+#[test]
+fn test_say_only_reaches_nearby_players() {
+    let mut server = ServerHandle::start(ServerConfig::loopback()).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let mut bob = connect(&mut server, "Bob");
+    let mut carol = connect(&mut server, "Carol");
+
+    server
+        .admin_command_sender()
+        .send(AdminCommand::Teleport {
+            name: "Carol".to_string(),
+            position: Vec2::ZERO,
+        })
+        .unwrap();
+    server.tick();
+    let reply = server.admin_reply_receiver().try_recv().unwrap();
+    assert!(reply.starts_with("Teleported Carol to "));
+
+    alice.send_chat_message(ChatChannel::Say, "psst").unwrap();
+    alice
+        .send_chat_message(ChatChannel::System, "I am the server now")
+        .unwrap();
+
+    let said =
+        ClientEvent::MessageReceived(ChatChannel::Say, "Alice".to_string(), "psst".to_string());
+    let bob_events = bob.get_event_receiver();
+    tick_until(
+        &mut server,
+        &mut [&mut alice, &mut bob, &mut carol],
+        |_, _| bob_events.try_iter().any(|event| event == said),
+    );
+
+    let carol_heard_anything = carol.get_event_receiver().try_iter().any(
+        |event| matches!(event, ClientEvent::MessageReceived(_, author, _) if author == "Alice"),
+    );
+    assert!(!carol_heard_anything);
+}
+
+#[test]
+fn test_late_joiners_receive_chat_history() {
+    let mut server = ServerHandle::start(ServerConfig::loopback()).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+
+    alice
+        .send_chat_message(ChatChannel::Global, "Anyone here?")
+        .unwrap();
+    let alice_events = alice.get_event_receiver();
+    tick_until(&mut server, &mut [&mut alice], |_, _| {
+        alice_events.try_iter().any(|event| {
+            matches!(
+                event,
+                ClientEvent::MessageReceived(ChatChannel::Global, _, _)
+            )
+        })
+    });
+
+    let mut bob = NetworkClient::default();
+    bob.connect_to(server.local_addr(), "Bob").unwrap();
+    let bob_events = bob.get_event_receiver();
+    let mut history = None;
+    tick_until(&mut server, &mut [&mut bob], |_, _| {
+        history = history.take().or_else(|| {
+            bob_events.try_iter().find_map(|event| match event {
+                ClientEvent::ChatHistory(entries) => Some(entries),
+                _ => None,
+            })
+        });
+        history.is_some()
+    });
+
+    let texts: Vec<String> = history
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.text)
+        .collect();
+    assert_eq!(texts, ["Alice has connected", "Anyone here?"]);
+}
+
+#[test]
+fn test_accepted_challenges_start_a_duel() {
+    let mut server = ServerHandle::start(ServerConfig::loopback()).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let mut bob = connect(&mut server, "Bob");
+
+    let (alice_events, bob_events) = (alice.get_event_receiver(), bob.get_event_receiver());
+    let mut bob_id = None;
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        bob_id = bob_id.or_else(|| {
+            alice_events.try_iter().find_map(|event| match event {
+                ClientEvent::SpawnEntity(id, _, false) => Some(id),
+                _ => None,
+            })
+        });
+        bob_id.is_some()
+    });
+    alice.send_challenge(bob_id.unwrap()).unwrap();
+
+    let mut alice_id = None;
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        alice_id = alice_id.or_else(|| {
+            bob_events.try_iter().find_map(|event| match event {
+                ClientEvent::ChallengeReceived(id) => Some(id),
+                _ => None,
+            })
+        });
+        alice_id.is_some()
+    });
+    bob.respond_to_challenge(alice_id.unwrap(), true).unwrap();
+
+    // The challenger goes first, so the only thing Bob can do is give up.
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        bob_events.try_iter().any(|event| {
+            matches!(event, ClientEvent::BattleStarted(snapshot) if snapshot.turn == alice_id.unwrap())
+        })
+    });
+
+    // Nobody can be challenged while they are busy fighting.
+    alice.send_challenge(bob_id.unwrap()).unwrap();
+    let refused = ClientEvent::MessageReceived(
+        ChatChannel::System,
+        "SERVER".to_string(),
+        "You are already in a duel.".to_string(),
+    );
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        alice_events.try_iter().any(|event| event == refused)
+    });
+
+    bob.send_battle_action(BattleAction::Attack).unwrap();
+    bob.send_battle_action(BattleAction::Forfeit).unwrap();
+
+    let mut alice_log = Vec::new();
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        alice_log.extend(alice_events.try_iter());
+        alice_log.contains(&ClientEvent::ModeChanged(ClientMode::Overworld))
+    });
+
+    let outcome = alice_log.iter().find_map(|event| match event {
+        ClientEvent::BattleEnded(outcome) => Some(*outcome),
+        _ => None,
+    });
+    let outcome = outcome.expect("The duel should have ended before returning to the overworld.");
+    assert_eq!(outcome.winner, alice_id.unwrap());
+    assert_eq!(outcome.reason, BattleEnd::Forfeited);
+    // Bob's attack out of turn was refused, so only the forfeit was played.
+    let actions: Vec<BattleAction> = alice_log
+        .iter()
+        .filter_map(|event| match event {
+            ClientEvent::BattleTurn(result, _) => Some(result.action),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(actions, [BattleAction::Forfeit]);
+}
+
+/// Start a duel between the two clients and have Bob give it up.
+fn forfeit_duel(server: &mut ServerHandle, alice: &mut NetworkClient, bob: &mut NetworkClient) {
+    start_duel(server, alice, bob);
+    bob.send_battle_action(BattleAction::Forfeit).unwrap();
+}
+
+/// Have Alice challenge Bob and Bob accept, returning once the duel
+/// has started.
+fn start_duel(
+    server: &mut ServerHandle,
+    alice: &mut NetworkClient,
+    bob: &mut NetworkClient,
+) -> (NetworkID, NetworkID) {
+    let (alice_id, bob_id) = player_ids(server, alice, bob);
+    alice.send_challenge(bob_id).unwrap();
+    let bob_events = bob.get_event_receiver();
+    tick_until(server, &mut [alice, bob], |_, _| {
+        bob_events
+            .try_iter()
+            .any(|event| event == ClientEvent::ChallengeReceived(alice_id))
+    });
+
+    bob.respond_to_challenge(alice_id, true).unwrap();
+    tick_until(server, &mut [alice, bob], |_, _| {
+        bob_events
+            .try_iter()
+            .any(|event| matches!(event, ClientEvent::BattleStarted(_)))
+    });
+    (alice_id, bob_id)
+}
+
+/// Wait for the leaderboard the client asked for.
+fn leaderboard(server: &mut ServerHandle, client: &mut NetworkClient) -> Leaderboard {
+    client.request_leaderboard().unwrap();
+
+    let events = client.get_event_receiver();
+    let mut leaderboard = None;
+    tick_until(server, &mut [client], |_, _| {
+        leaderboard = leaderboard.take().or_else(|| {
+            events.try_iter().find_map(|event| match event {
+                ClientEvent::Leaderboard(leaderboard) => Some(leaderboard),
+                _ => None,
+            })
+        });
+        leaderboard.is_some()
+    });
+    leaderboard.unwrap()
+}
+
+#[test]
+fn test_idle_duelists_forfeit() {
+    let mut server = ServerHandle::start(ServerConfig {
+        turn_timeout_secs: 1,
+        ..ServerConfig::loopback()
+    })
+    .unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let mut bob = connect(&mut server, "Bob");
+    let (alice_id, bob_id) = start_duel(&mut server, &mut alice, &mut bob);
+
+    // Alice goes first but never acts.
+    let bob_events = bob.get_event_receiver();
+    let mut outcome = None;
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        outcome = outcome.or_else(|| {
+            bob_events.try_iter().find_map(|event| match event {
+                ClientEvent::BattleEnded(outcome) => Some(outcome),
+                _ => None,
+            })
+        });
+        outcome.is_some()
+    });
+
+    let outcome = outcome.unwrap();
+    assert_eq!((outcome.winner, outcome.loser), (bob_id, alice_id));
+    assert_eq!(outcome.reason, BattleEnd::Forfeited);
+}
+
+#[test]
+fn test_duels_are_rated_and_ratings_are_saved() {
+    let ratings_path =
+        std::env::temp_dir().join(format!("shackle-ratings-{}.toml", std::process::id()));
+    let config = ServerConfig {
+        ratings_path: Some(ratings_path.clone()),
+        ..ServerConfig::loopback()
+    };
+
+    let mut server = ServerHandle::start(config.clone()).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let mut bob = connect(&mut server, "Bob");
+    forfeit_duel(&mut server, &mut alice, &mut bob);
+
+    let alice_events = alice.get_event_receiver();
+    let notice = ClientEvent::MessageReceived(
+        ChatChannel::System,
+        "SERVER".to_string(),
+        "Your rating is now 1216 (+16).".to_string(),
+    );
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        alice_events.try_iter().any(|event| event == notice)
+    });
+
+    let board = leaderboard(&mut server, &mut bob);
+    let top: Vec<(&str, i32)> = board
+        .top
+        .iter()
+        .map(|entry| (entry.name.as_str(), entry.rating))
+        .collect();
+    assert_eq!(top, [("Alice", 1216), ("Bob", 1184)]);
+    assert_eq!(board.own.map(|own| (own.rank, own.losses)), Some((2, 1)));
+
+    // The ratings are saved as soon as the duel ends, not just on a
+    // clean shutdown.
+    let saved = std::fs::read_to_string(&ratings_path).unwrap();
+    assert!(saved.contains("rating = 1216"));
+    server.stop().unwrap();
+
+    let mut server = ServerHandle::start(config).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let board = leaderboard(&mut server, &mut alice);
+    std::fs::remove_file(&ratings_path).unwrap();
+
+    assert_eq!(board.own.map(|own| (own.rank, own.rating)), Some((1, 1216)));
+}
+
+#[test]
+fn test_duels_can_be_watched() {
+    let mut server = ServerHandle::start(ServerConfig::loopback()).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let mut bob = connect(&mut server, "Bob");
+    let mut carol = connect(&mut server, "Carol");
+    let notice = |text: &str| {
+        ClientEvent::MessageReceived(ChatChannel::System, "SERVER".to_string(), text.to_string())
+    };
+
+    let (alice_id, bob_id) = player_ids(&mut server, &mut alice, &mut bob);
+    carol.spectate_duel(alice_id).unwrap();
+    let carol_events = carol.get_event_receiver();
+    tick_until(&mut server, &mut [&mut carol], |_, _| {
+        carol_events
+            .try_iter()
+            .any(|event| event == notice("That player is not in a duel."))
+    });
+
+    start_duel(&mut server, &mut alice, &mut bob);
+    carol.spectate_duel(bob_id).unwrap();
+    let alice_events = alice.get_event_receiver();
+    let mut carol_log = Vec::new();
+    tick_until(
+        &mut server,
+        &mut [&mut alice, &mut bob, &mut carol],
+        |_, _| {
+            carol_log.extend(carol_events.try_iter());
+            alice_events
+                .try_iter()
+                .any(|event| event == ClientEvent::SpectatorsChanged(1))
+        },
+    );
+    assert!(carol_log.contains(&ClientEvent::ModeChanged(ClientMode::Spectating)));
+    assert!(carol_log.iter().any(
+        |event| matches!(event, ClientEvent::BattleStarted(snapshot) if snapshot.turn == alice_id)
+    ));
+
+    // Spectators cannot act, but see every turn and the end of the duel.
+    carol.send_battle_action(BattleAction::Attack).unwrap();
+    tick_until(
+        &mut server,
+        &mut [&mut alice, &mut bob, &mut carol],
+        |_, _| {
+            carol_events
+                .try_iter()
+                .any(|event| event == notice("You are not in a duel."))
+        },
+    );
+
+    alice.send_battle_action(BattleAction::Defend).unwrap();
+    bob.send_battle_action(BattleAction::Forfeit).unwrap();
+    let mut carol_log = Vec::new();
+    tick_until(
+        &mut server,
+        &mut [&mut alice, &mut bob, &mut carol],
+        |_, _| {
+            carol_log.extend(carol_events.try_iter());
+            carol_log.contains(&ClientEvent::ModeChanged(ClientMode::Overworld))
+        },
+    );
+
+    let actions: Vec<BattleAction> = carol_log
+        .iter()
+        .filter_map(|event| match event {
+            ClientEvent::BattleTurn(result, _) => Some(result.action),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(actions, [BattleAction::Defend, BattleAction::Forfeit]);
+    assert!(carol_log.iter().any(|event| matches!(
+        event,
+        ClientEvent::BattleEnded(outcome) if outcome.winner == alice_id
+    )));
+}
+
+/// Wait until both clients know which player they are.
+fn player_ids(
+    server: &mut ServerHandle,
+    alice: &mut NetworkClient,
+    bob: &mut NetworkClient,
+) -> (NetworkID, NetworkID) {
+    tick_until(server, &mut [alice, bob], |_, clients| {
+        clients
+            .iter()
+            .all(|client| client.get_player_id().is_some())
+    });
+    (alice.get_player_id().unwrap(), bob.get_player_id().unwrap())
+}
+
+#[test]
+fn test_challenges_can_be_withdrawn_or_declined() {
+    let mut server = ServerHandle::start(ServerConfig::loopback()).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let mut bob = connect(&mut server, "Bob");
+    let (alice_id, bob_id) = player_ids(&mut server, &mut alice, &mut bob);
+
+    alice.send_challenge(bob_id).unwrap();
+    alice.cancel_challenge(bob_id).unwrap();
+
+    let resolved = |result| ClientEvent::ChallengeResolved {
+        challenger: alice_id,
+        target: bob_id,
+        result,
+    };
+    let (alice_events, bob_events) = (alice.get_event_receiver(), bob.get_event_receiver());
+    let mut bob_log = Vec::new();
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        bob_log.extend(bob_events.try_iter());
+        bob_log.contains(&resolved(ChallengeResult::Cancelled))
+    });
+
+    alice.send_challenge(bob_id).unwrap();
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        bob_log.extend(bob_events.try_iter());
+        let challenges = bob_log
+            .iter()
+            .filter(|event| **event == ClientEvent::ChallengeReceived(alice_id))
+            .count();
+        challenges == 2
+    });
+    bob.respond_to_challenge(alice_id, false).unwrap();
+
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        alice_events
+            .try_iter()
+            .any(|event| event == resolved(ChallengeResult::Declined))
+    });
+}
+
+#[test]
+fn test_unanswered_challenges_expire() {
+    let config = ServerConfig {
+        challenge_timeout_secs: 0,
+        ..ServerConfig::loopback()
+    };
+    let mut server = ServerHandle::start(config).unwrap();
+    let mut alice = connect(&mut server, "Alice");
+    let mut bob = connect(&mut server, "Bob");
+    let (alice_id, bob_id) = player_ids(&mut server, &mut alice, &mut bob);
+
+    alice.send_challenge(bob_id).unwrap();
+    let expired = ClientEvent::ChallengeResolved {
+        challenger: alice_id,
+        target: bob_id,
+        result: ChallengeResult::Expired,
+    };
+    let alice_events = alice.get_event_receiver();
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        alice_events.try_iter().any(|event| event == expired)
+    });
+
+    // Answering too late does not start a duel.
+    bob.respond_to_challenge(alice_id, true).unwrap();
+    let closed = ClientEvent::MessageReceived(
+        ChatChannel::System,
+        "SERVER".to_string(),
+        "That challenge is no longer open.".to_string(),
+    );
+    let bob_events = bob.get_event_receiver();
+    tick_until(&mut server, &mut [&mut alice, &mut bob], |_, _| {
+        bob_events.try_iter().any(|event| event == closed)
+    });
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use client::{functionality::DuelingClient, ClientEvent, ConnectionStatus, NetworkClient};
+use common::{
+    battle::{BattleAction, BattleSnapshot},
+    math::Vec2,
+    messages::ChatChannel,
+    NetworkID,
+};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::stats::Report;
+
+/// How often each bot does things, on average.
+#[derive(Clone, Copy)]
+pub struct Behaviour {
+    pub chat_interval: Duration,
+    pub challenge_interval: Duration,
+    pub wander_interval: Duration,
+    pub connect_timeout: Duration,
+}
+
+/// A single headless player.
+pub struct Bot {
+    name: String,
+    client: NetworkClient,
+    behaviour: Behaviour,
+    connect_started: Instant,
+    connected: bool,
+    finished: bool,
+    direction: Vec2,
+    next_wander: Instant,
+    next_chat: Instant,
+    next_challenge: Instant,
+    chat_counter: u32,
+    /// Chat messages waiting to be echoed back, by text.
+    pending_chats: HashMap<String, Instant>,
+    /// When each unacknowledged movement input was sent, by sequence.
+    pending_moves: HashMap<u32, Instant>,
+    others: Vec<NetworkID>,
+    /// Duels have no time limit, so a bot in one has to keep playing.
+    in_battle: bool,
+    report: Report,
+}
+
+impl Bot {
+    pub fn connect(name: String, server_addr: SocketAddr, behaviour: Behaviour) -> Self {
+        let now = Instant::now();
+        let mut report = Report {
+            bots: 1,
+            ..Default::default()
+        };
+
+        let mut client = NetworkClient::default();
+        let finished = match client.connect_to(server_addr, &name) {
+            Ok(()) => false,
+            Err(err) => {
+                report.record_failure(format!("Could not connect: {err:?}"));
+                true
+            }
+        };
+
+        Self {
+            name,
+            client,
+            behaviour,
+            connect_started: now,
+            connected: false,
+            finished,
+            direction: Vec2::ZERO,
+            next_wander: now,
+            next_chat: now + behaviour.chat_interval,
+            next_challenge: now + behaviour.challenge_interval,
+            chat_counter: 0,
+            pending_chats: HashMap::new(),
+            pending_moves: HashMap::new(),
+            others: Vec::new(),
+            in_battle: false,
+            report,
+        }
+    }
+
+    /// Handle everything the server has sent. Bots that are winding
+    /// down only listen for replies and stop doing anything new.
+    pub fn tick(&mut self, rng: &mut impl Rng, winding_down: bool) {
+        if self.finished {
+            return;
+        }
+
+        let now = Instant::now();
+        if let Err(err) = self.client.receive_messages() {
+            self.fail(format!("Could not receive messages: {err:?}"));
+            return;
+        }
+
+        match self.client.connection_status() {
+            ConnectionStatus::Connected if !self.connected => {
+                self.connected = true;
+                self.report.connected = 1;
+                self.report
+                    .connect_latency
+                    .record(now - self.connect_started);
+            }
+            ConnectionStatus::Connecting => {
+                if now - self.connect_started > self.behaviour.connect_timeout {
+                    self.fail("Timed out connecting".to_string());
+                }
+                return;
+            }
+            ConnectionStatus::Failed(reason) => {
+                self.fail(format!("Disconnected: {reason}"));
+                return;
+            }
+            _ => {}
+        }
+
+        self.handle_events(now, rng);
+        if !winding_down {
+            self.act(now, rng);
+        }
+    }
+
+    fn handle_events(&mut self, now: Instant, rng: &mut impl Rng) {
+        self.client
+            .get_event_receiver()
+            .try_iter()
+            .for_each(|event| match event {
+                ClientEvent::SpawnEntity(id, _, false) => self.others.push(id),
+                ClientEvent::DespawnEntity(id) => self.others.retain(|other| *other != id),
+                ClientEvent::MoveAcknowledged(sequence, _) => {
+                    if let Some(sent) = self.pending_moves.remove(&sequence) {
+                        self.report.move_round_trip.record(now - sent);
+                    }
+                    // Acknowledgements cover every earlier input too.
+                    self.pending_moves.retain(|pending, _| *pending > sequence);
+                }
+                ClientEvent::MessageReceived(_, author, text) if author == self.name => {
+                    if let Some(sent) = self.pending_chats.remove(&text) {
+                        self.report.chat_round_trip.record(now - sent);
+                    }
+                }
+                ClientEvent::ChallengeReceived(challenger) => {
+                    self.report.challenges_received += 1;
+                    let accept = rng.gen_bool(0.5);
+                    if let Err(err) = self.client.respond_to_challenge(challenger, accept) {
+                        self.report
+                            .record_failure(format!("Could not answer a challenge: {err:?}"));
+                    }
+                }
+                ClientEvent::BattleStarted(snapshot) | ClientEvent::BattleTurn(_, snapshot) => {
+                    self.in_battle = true;
+                    self.take_turn(&snapshot, rng);
+                }
+                ClientEvent::BattleEnded(_) => {
+                    self.in_battle = false;
+                    self.report.battles_finished += 1;
+                }
+                _ => {}
+            });
+    }
+
+    fn take_turn(&mut self, snapshot: &BattleSnapshot, rng: &mut impl Rng) {
+        if self.client.get_player_id() != Some(snapshot.turn) {
+            return;
+        }
+
+        let ability_used = snapshot
+            .combatant(snapshot.turn)
+            .is_none_or(|own| own.ability_used);
+        let action = match rng.gen_range(0..4) {
+            0 => BattleAction::Defend,
+            1 if !ability_used => BattleAction::Ability,
+            _ => BattleAction::Attack,
+        };
+        if let Err(err) = self.client.send_battle_action(action) {
+            self.report
+                .record_failure(format!("Could not take a turn: {err:?}"));
+        }
+    }
+
+    fn act(&mut self, now: Instant, rng: &mut impl Rng) {
+        // The server ignores movement from players who are in a duel.
+        if self.in_battle {
+            return;
+        }
+
+        if now >= self.next_wander {
+            self.direction = Vec2::new(rng.gen_range(-1..=1) as f32, rng.gen_range(-1..=1) as f32);
+            self.next_wander = now + jitter(self.behaviour.wander_interval, rng);
+        }
+
+        match self.client.move_player(self.direction) {
+            Ok(input) => {
+                self.pending_moves.insert(input.sequence, now);
+            }
+            Err(err) => self
+                .report
+                .record_failure(format!("Could not move: {err:?}")),
+        }
+
+        if now >= self.next_chat {
+            self.chat_counter += 1;
+            let text = format!("{} says hello #{}", self.name, self.chat_counter);
+            match self.client.send_chat_message(ChatChannel::Global, &text) {
+                Ok(()) => {
+                    self.pending_chats.insert(text, now);
+                }
+                Err(err) => self
+                    .report
+                    .record_failure(format!("Could not chat: {err:?}")),
+            }
+            self.next_chat = now + jitter(self.behaviour.chat_interval, rng);
+        }
+
+        if now >= self.next_challenge {
+            if let Some(target) = self.others.choose(rng) {
+                match self.client.send_challenge(*target) {
+                    Ok(()) => self.report.challenges_sent += 1,
+                    Err(err) => self
+                        .report
+                        .record_failure(format!("Could not challenge: {err:?}")),
+                }
+            }
+            self.next_challenge = now + jitter(self.behaviour.challenge_interval, rng);
+        }
+    }
+
+    fn fail(&mut self, failure: String) {
+        log::warn!("{}: {failure}", self.name);
+        self.report.record_failure(failure);
+        self.finished = true;
+    }
+
+    /// Stop the bot and hand over everything it measured. Anything
+    /// still waiting for a reply counts as lost.
+    pub fn finish(mut self) -> Report {
+        let lost_chats = self.pending_chats.len();
+        if lost_chats > 0 {
+            *self
+                .report
+                .failures
+                .entry("Chat message never echoed".to_string())
+                .or_insert(0) += lost_chats;
+        }
+        self.report
+    }
+}
+
+/// Vary an interval by up to half in either direction so bots do
+/// not all act on the same tick.
+fn jitter(interval: Duration, rng: &mut impl Rng) -> Duration {
+    interval.mul_f64(rng.gen_range(0.5..1.5))
+}
@@ -0,0 +1,121 @@
+mod bot;
+mod stats;
+
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use common::validation::validate_username;
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::{
+    bot::{Behaviour, Bot},
+    stats::Report,
+};
+
+/// Connect a crowd of headless players to a server and report how
+/// well it copes.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// The server to connect to.
+    #[arg(short, long, default_value = "127.0.0.1:27008")]
+    server: SocketAddr,
+
+    /// How many bots to connect.
+    #[arg(short, long, default_value_t = 10)]
+    bots: usize,
+
+    /// How long to run for, in seconds.
+    #[arg(short, long, default_value_t = 30)]
+    duration: u64,
+
+    /// How long to wait between connecting each bot, in milliseconds.
+    #[arg(long, default_value_t = 50)]
+    stagger: u64,
+
+    /// Bot ticks per second.
+    #[arg(long, default_value_t = 60)]
+    tick_rate: u32,
+
+    /// Average seconds between each bot's chat messages.
+    #[arg(long, default_value_t = 5.0)]
+    chat_interval: f64,
+
+    /// Average seconds between each bot's duel challenges.
+    #[arg(long, default_value_t = 15.0)]
+    challenge_interval: f64,
+
+    /// Names are this followed by a random tag and the bot's number.
+    #[arg(long, default_value = "Bot")]
+    name_prefix: String,
+}
+
+/// How long bots keep listening for replies after the run ends.
+const WIND_DOWN: Duration = Duration::from_secs(2);
+
+/// A name that is valid and unlikely to clash with a previous run
+/// against the same server.
+fn generate_name(prefix: &str, index: usize, rng: &mut impl Rng) -> String {
+    loop {
+        let tag: String = (0..3).map(|_| rng.sample(Alphanumeric) as char).collect();
+        let name = format!("{prefix}{tag}{index}");
+
+        if validate_username(&name).is_ok() {
+            return name;
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+    let args = Args::parse();
+
+    if let Err(err) = validate_username(&format!("{}AAA{}", args.name_prefix, args.bots)) {
+        eprintln!("The name prefix cannot be used: {err}");
+        std::process::exit(1);
+    }
+
+    let behaviour = Behaviour {
+        chat_interval: Duration::from_secs_f64(args.chat_interval),
+        challenge_interval: Duration::from_secs_f64(args.challenge_interval),
+        wander_interval: Duration::from_secs(2),
+        connect_timeout: Duration::from_secs(10),
+    };
+    let tick_duration = Duration::from_secs_f64(1.0 / args.tick_rate.max(1) as f64);
+    let stagger = Duration::from_millis(args.stagger);
+
+    println!(
+        "Running {} bots against {} for {}s...",
+        args.bots, args.server, args.duration
+    );
+
+    let mut rng = rand::thread_rng();
+    let mut bots: Vec<Bot> = Vec::with_capacity(args.bots);
+    let start = Instant::now();
+    let end = start + Duration::from_secs(args.duration);
+    let mut next_connect = start;
+
+    while Instant::now() < end + WIND_DOWN {
+        let now = Instant::now();
+
+        if bots.len() < args.bots && now >= next_connect && now < end {
+            let name = generate_name(&args.name_prefix, bots.len(), &mut rng);
+            bots.push(Bot::connect(name, args.server, behaviour));
+            next_connect = now + stagger;
+        }
+
+        let winding_down = now >= end;
+        bots.iter_mut()
+            .for_each(|bot| bot.tick(&mut rng, winding_down));
+
+        thread::sleep(tick_duration);
+    }
+
+    let mut report = Report::default();
+    bots.into_iter().for_each(|bot| report.merge(bot.finish()));
+    report.print();
+}
@@ -0,0 +1,143 @@
+use std::{collections::HashMap, fmt::Display, time::Duration};
+
+/// A set of timings that can be summarised once a run is over.
+#[derive(Default)]
+pub struct Samples(Vec<Duration>);
+
+impl Samples {
+    pub fn record(&mut self, sample: Duration) {
+        self.0.push(sample);
+    }
+
+    pub fn extend(&mut self, other: Samples) {
+        self.0.extend(other.0);
+    }
+
+    pub fn summary(&self) -> Option<Summary> {
+        let mut sorted = self.0.clone();
+        sorted.sort();
+
+        let total: Duration = sorted.iter().sum();
+        let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+
+        Some(Summary {
+            count: sorted.len(),
+            min: *sorted.first()?,
+            mean: total / sorted.len() as u32,
+            p50: percentile(50),
+            p95: percentile(95),
+            max: *sorted.last()?,
+        })
+    }
+}
+
+pub struct Summary {
+    count: usize,
+    min: Duration,
+    mean: Duration,
+    p50: Duration,
+    p95: Duration,
+    max: Duration,
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "n={} min={:.1?} mean={:.1?} p50={:.1?} p95={:.1?} max={:.1?}",
+            self.count, self.min, self.mean, self.p50, self.p95, self.max
+        )
+    }
+}
+
+/// Everything measured by every bot over a run.
+#[derive(Default)]
+pub struct Report {
+    pub bots: usize,
+    pub connected: usize,
+    pub connect_latency: Samples,
+    pub chat_round_trip: Samples,
+    pub move_round_trip: Samples,
+    pub challenges_sent: usize,
+    pub challenges_received: usize,
+    /// Counted by both fighters, so each duel appears twice.
+    pub battles_finished: usize,
+    pub failures: HashMap<String, usize>,
+}
+
+impl Report {
+    pub fn record_failure(&mut self, failure: impl Into<String>) {
+        *self.failures.entry(failure.into()).or_insert(0) += 1;
+    }
+
+    pub fn merge(&mut self, other: Report) {
+        self.bots += other.bots;
+        self.connected += other.connected;
+        self.connect_latency.extend(other.connect_latency);
+        self.chat_round_trip.extend(other.chat_round_trip);
+        self.move_round_trip.extend(other.move_round_trip);
+        self.challenges_sent += other.challenges_sent;
+        self.challenges_received += other.challenges_received;
+        self.battles_finished += other.battles_finished;
+        other
+            .failures
+            .into_iter()
+            .for_each(|(failure, count)| *self.failures.entry(failure).or_insert(0) += count);
+    }
+
+    pub fn print(&self) {
+        println!("Bots connected: {}/{}", self.connected, self.bots);
+
+        let timings = [
+            ("Connect latency", &self.connect_latency),
+            ("Chat round trip", &self.chat_round_trip),
+            ("Move acknowledgement", &self.move_round_trip),
+        ];
+        timings
+            .iter()
+            .for_each(|(name, samples)| match samples.summary() {
+                Some(summary) => println!("{name}: {summary}"),
+                None => println!("{name}: no samples"),
+            });
+
+        println!(
+            "Challenges sent: {}, received: {}",
+            self.challenges_sent, self.challenges_received
+        );
+        println!("Duels finished: {}", self.battles_finished / 2);
+
+        if self.failures.is_empty() {
+            println!("No failures.");
+        } else {
+            println!("Failures:");
+            let mut failures: Vec<_> = self.failures.iter().collect();
+            failures.sort_by(|a, b| b.1.cmp(a.1));
+            failures.iter().for_each(|(failure, count)| {
+                println!("  {count} x {failure}");
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut samples = Samples::default();
+        (1..=100).for_each(|ms| samples.record(Duration::from_millis(ms)));
+
+        let summary = samples.summary().unwrap();
+
+        assert_eq!(summary.min, Duration::from_millis(1));
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p95, Duration::from_millis(95));
+        assert_eq!(summary.max, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_empty_summary() {
+        assert!(Samples::default().summary().is_none());
+    }
+}